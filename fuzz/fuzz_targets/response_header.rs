@@ -0,0 +1,32 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use td3_pattern::device::{check_response, PROFILES};
+
+// reply of device which could be cut short, have wrong header or miss F7 at the end
+#[derive(Arbitrary, Debug)]
+struct Response {
+    profile: u8,
+    header_bytes: u8,
+    wrong_header_byte: Option<(u8, u8)>,
+    data: Vec<u8>,
+    end: Option<u8>,
+}
+
+fuzz_target!(|response: Response| {
+    let header = PROFILES[response.profile as usize % PROFILES.len()].header;
+    let mut msg = header[0..(response.header_bytes as usize).min(header.len())].to_vec();
+    if let Some((at, value)) = response.wrong_header_byte {
+        if let Some(byte) = msg.get_mut(at as usize) {
+            *byte = value;
+        }
+    }
+    msg.extend_from_slice(&response.data);
+    msg.extend(response.end);
+    if let Ok(data) = check_response(&msg, header, "fuzz") {
+        assert!(msg.starts_with(header));
+        assert_eq!(msg.last(), Some(&0xF7));
+        assert_eq!(data, msg[header.len()..msg.len() - 1]);
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use td3_pattern::pattern::{decode_pattern, pattern_to_sysex, Decode};
+use td3_pattern::track::{sysex_to_track, track_to_sysex};
+
+// payload as it comes from MIDI or .syx file after header check, decoded pattern is always encoded back
+fuzz_target!(|msg: &[u8]| {
+    for decode in [Decode::Strict, Decode::Lenient] {
+        if let Ok(pattern) = decode_pattern(msg, None, decode) {
+            let sysex = pattern_to_sysex(&pattern, Default::default());
+            decode_pattern(&sysex, None, Decode::Strict).unwrap();
+        }
+    }
+    if let Ok(track) = sysex_to_track(msg, None) {
+        sysex_to_track(&track_to_sysex(&track, msg[1]).unwrap(), Some(msg[1])).unwrap();
+    }
+});
@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use td3_pattern::bank::{bank_to_string, string_to_bank};
+use td3_pattern::document::format_pattern;
+use td3_pattern::lint::lint;
+use td3_pattern::pattern::{pattern_to_string_with_metadata, string_to_pattern_with_metadata};
+use td3_pattern::track::string_to_track;
+
+// anything read from a file is either an error or pattern which is written and read back the same
+fuzz_target!(|data: &[u8]| {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    if let Ok((pattern, metadata)) = string_to_pattern_with_metadata(text.to_owned()) {
+        let written = pattern_to_string_with_metadata(&pattern, &metadata);
+        let (read, read_metadata) = string_to_pattern_with_metadata(written.clone()).unwrap();
+        assert_eq!(pattern_to_string_with_metadata(&read, &read_metadata), written);
+        format_pattern("fuzz", text).unwrap();
+        lint(&pattern);
+    }
+    if let Ok(bank) = string_to_bank(text.to_owned()) {
+        string_to_bank(bank_to_string(&bank, true)).unwrap();
+    }
+    let _ = string_to_track(text.to_owned());
+});
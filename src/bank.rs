@@ -0,0 +1,154 @@
+use std::error::Error;
+
+use crate::diagnostic::Diagnostics;
+use crate::metadata::Metadata;
+use crate::pattern::{parse_pattern, pattern_to_string_with_unknown, Pattern};
+use crate::slot::SlotAddress;
+
+// bank file is many pattern files in one, every one starts with [Group 2 Pattern 3B] line:
+//
+// [Group 1 Pattern 1A]
+// TD-3 Pattern
+// ...
+// [Group 1 Pattern 2A]
+// TD-3 Pattern v2
+// ...
+pub struct BankEntry {
+    pub slot: SlotAddress,
+    pub pattern: Pattern,
+    pub metadata: Metadata,
+}
+
+// [Group 2 Pattern 3B]
+pub fn slot_header(slot: SlotAddress) -> String {
+    format!("[Group {} Pattern {}{}]", slot.group() + 1, slot.number() + 1, slot.side())
+}
+
+// None if line isn't a header, any case and spacing is accepted
+pub(crate) fn parse_slot_header(line: &str) -> Option<Result<SlotAddress, Box<dyn Error>>> {
+    let line = line.trim();
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.to_lowercase();
+    let slot = match inner.split_whitespace().collect::<Vec<&str>>().as_slice() {
+        ["group", group, "pattern", pattern] => format!("{}:{}", group, pattern).parse::<SlotAddress>(),
+        _ => Err(format!("Expecting slot header like [Group 2 Pattern 3B], read: {}", line).into()),
+    };
+    Some(slot)
+}
+
+// first line which isn't blank or comment is a slot header
+pub fn is_bank(text: &str) -> bool {
+    text.lines()
+        .map(|line| line.find("//").map_or(line, |x| &line[0..x]).trim())
+        .find(|line| !line.is_empty())
+        .is_some_and(|line| line.starts_with('['))
+}
+
+// unknown writes Unknown line for every pattern, see pattern_to_string_with_unknown
+pub fn bank_to_string(entries: &[BankEntry], unknown: bool) -> String {
+    let sections = entries.iter().map(|entry| {
+        let pattern = pattern_to_string_with_unknown(&entry.pattern, &entry.metadata, unknown);
+        format!("{}\n{}", slot_header(entry.slot), pattern)
+    });
+    sections.collect::<Vec<String>>().join("\n")
+}
+
+pub fn string_to_bank(string_bank: String) -> Result<Vec<BankEntry>, Box<dyn Error>> {
+    Ok(parse_bank(&string_bank)?)
+}
+
+// same, errors are prefixed with file name
+pub fn file_to_bank(filename: &str, string_bank: String) -> Result<Vec<BankEntry>, Box<dyn Error>> {
+    match parse_bank(&string_bank) {
+        Ok(entries) => Ok(entries),
+        Err(diagnostics) => Err(Box::new(Diagnostics { file: Some(filename.to_owned()), ..diagnostics })),
+    }
+}
+
+// every section is parsed as pattern file, errors in all of them are reported with line numbers of bank file
+fn parse_bank(string_bank: &str) -> Result<Vec<BankEntry>, Diagnostics> {
+    let mut diagnostics: Diagnostics = Default::default();
+    // header line number, header, slot and lines of pattern which follow it, slot is None for wrong header
+    let mut sections: Vec<(usize, &str, Option<SlotAddress>, Vec<&str>)> = Vec::new();
+    for (n, line) in string_bank.lines().enumerate() {
+        let code = line.find("//").map_or(line, |x| &line[0..x]);
+        match (parse_slot_header(code), sections.last_mut()) {
+            (Some(Ok(slot)), _) => {
+                if sections.iter().any(|(_, _, s, _)| *s == Some(slot)) {
+                    let message = format!("{} is given twice", slot_header(slot));
+                    diagnostics.push(n + 1, 1, line.trim_end().chars().count(), message, line);
+                }
+                sections.push((n + 1, line, Some(slot), Vec::new()));
+            }
+            (Some(Err(e)), _) => {
+                diagnostics.push(n + 1, 1, line.trim_end().chars().count(), e.to_string(), line);
+                // pattern is still checked
+                sections.push((n + 1, line, None, Vec::new()));
+            }
+            (None, Some((_, _, _, lines))) => lines.push(line),
+            (None, None) if code.trim().is_empty() => (),
+            (None, None) => {
+                let message = String::from("Expecting slot header like [Group 2 Pattern 3B] before pattern");
+                diagnostics.push(n + 1, 1, line.trim_end().chars().count(), message, line);
+            }
+        }
+    }
+    if sections.is_empty() && diagnostics.is_empty() {
+        diagnostics.push_file(String::from("Bank has no patterns, expecting [Group 2 Pattern 3B] lines"));
+    }
+    let mut entries = Vec::new();
+    for (start, header, slot, lines) in sections.into_iter() {
+        match parse_pattern(&lines.join("\n")) {
+            Ok((pattern, metadata)) => {
+                if let Some(slot) = slot {
+                    entries.push(BankEntry { slot, pattern, metadata })
+                }
+            }
+            Err(section) => {
+                for mut diagnostic in section.diagnostics.into_iter() {
+                    // problems with section as a whole are shown on its header
+                    if diagnostic.line == 0 {
+                        diagnostic.column = 1;
+                        diagnostic.width = header.trim_end().chars().count();
+                        diagnostic.source = header.to_owned();
+                        diagnostic.line = start;
+                    } else {
+                        diagnostic.line += start;
+                    }
+                    diagnostics.diagnostics.push(diagnostic);
+                }
+            }
+        }
+    }
+    diagnostics.diagnostics.sort_by_key(|d| (d.line == 0, d.line, d.column));
+    match diagnostics.is_empty() {
+        true => Ok(entries),
+        false => Err(diagnostics),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::tests::{pattern_strategy, slot_strategy};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn bank_round_trip(patterns in prop::collection::vec((slot_strategy(), pattern_strategy()), 1..5)) {
+            let mut entries: Vec<BankEntry> = Vec::new();
+            for (slot, pattern) in patterns {
+                if !entries.iter().any(|e| e.slot == slot) {
+                    entries.push(BankEntry { slot, pattern, metadata: Default::default() });
+                }
+            }
+            let text = bank_to_string(&entries, false);
+            let read = string_to_bank(text.clone()).unwrap();
+            prop_assert_eq!(read.len(), entries.len());
+            for (read, entry) in read.iter().zip(entries.iter()) {
+                prop_assert_eq!(read.slot, entry.slot);
+                prop_assert_eq!(&read.pattern, &entry.pattern);
+            }
+            prop_assert_eq!(bank_to_string(&read, false), text);
+        }
+    }
+}
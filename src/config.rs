@@ -1,11 +1,30 @@
+use std::collections::HashMap;
+use std::env;
 use std::error::Error;
 use std::fmt::Debug;
+use std::fs::read_to_string;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::pattern::Decode;
+use crate::slot::parse_group;
+pub use crate::slot::{all_slots, parse_slot_args, parse_slots, slot_path, Side, SlotAddress, GROUPS, PATTERNS};
+use crate::track::TRACKS;
 
 pub enum Mode {
     Download,
     Upload,
-    // List,
+    Sync,
+    Watch,
+    Settings,
+    List,
+    Info,
+    Backup,
+    Restore,
+    Help,
+    Fmt,
+    Lint,
 }
 
 impl FromStr for Mode {
@@ -14,6 +33,16 @@ impl FromStr for Mode {
         match input {
             "upload" => Ok(Mode::Upload),
             "download" => Ok(Mode::Download),
+            "sync" => Ok(Mode::Sync),
+            "watch" => Ok(Mode::Watch),
+            "settings" => Ok(Mode::Settings),
+            "list" => Ok(Mode::List),
+            "info" => Ok(Mode::Info),
+            "backup" => Ok(Mode::Backup),
+            "restore" => Ok(Mode::Restore),
+            "help" => Ok(Mode::Help),
+            "fmt" => Ok(Mode::Fmt),
+            "lint" => Ok(Mode::Lint),
             _ => Err(()),
         }
     }
@@ -24,6 +53,70 @@ impl Debug for Mode {
         match *self {
             Mode::Download => write!(f, "Download"),
             Mode::Upload => write!(f, "Upload"),
+            Mode::Sync => write!(f, "Sync"),
+            Mode::Watch => write!(f, "Watch"),
+            Mode::Settings => write!(f, "Settings"),
+            Mode::List => write!(f, "List"),
+            Mode::Info => write!(f, "Info"),
+            Mode::Backup => write!(f, "Backup"),
+            Mode::Restore => write!(f, "Restore"),
+            Mode::Help => write!(f, "Help"),
+            Mode::Fmt => write!(f, "Fmt"),
+            Mode::Lint => write!(f, "Lint"),
+        }
+    }
+}
+
+// sync direction, pull is device -> directory, push is directory -> device
+#[derive(Debug)]
+pub enum Direction {
+    Pull,
+    Push,
+}
+
+impl FromStr for Direction {
+    type Err = ();
+    fn from_str(input: &str) -> Result<Direction, Self::Err> {
+        match input {
+            "pull" => Ok(Direction::Pull),
+            "push" => Ok(Direction::Push),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsAction {
+    Show,
+    Set,
+}
+
+impl FromStr for SettingsAction {
+    type Err = ();
+    fn from_str(input: &str) -> Result<SettingsAction, Self::Err> {
+        match input {
+            "show" => Ok(SettingsAction::Show),
+            "set" => Ok(SettingsAction::Set),
+            _ => Err(()),
+        }
+    }
+}
+
+// output format for downloaded patterns
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    Text,
+    // raw sysex message as it comes from device, could be loaded by other sysex tools
+    Sysex,
+}
+
+impl FromStr for Format {
+    type Err = ();
+    fn from_str(input: &str) -> Result<Format, Self::Err> {
+        match input {
+            "text" => Ok(Format::Text),
+            "syx" => Ok(Format::Sysex),
+            _ => Err(()),
         }
     }
 }
@@ -34,15 +127,238 @@ pub struct Config {
     pub out_port: String,
     pub mode: Mode,
     pub filename: String,
-    pub group: u8,
-    pub pnum: u8,
-    pub ab: u8,
+    pub dir: String,
+    pub direction: Direction,
+    pub dry_run: bool,
+    // apply sync plan without asking
+    pub yes: bool,
+    // send writes of features which aren't confirmed on device firmware
+    pub allow_unconfirmed: bool,
+    // every slot command applies to, in order given
+    pub slots: Vec<SlotAddress>,
+    pub track: Option<u8>,
+    // device profile override for unknown models
+    pub model: Option<String>,
+    pub settings_action: SettingsAction,
+    // setting=value pairs from command line
+    pub settings: Vec<String>,
+    pub format: Format,
+    // command to show help for, empty for general usage
+    pub help: Option<String>,
+    // files and directories for fmt and lint
+    pub paths: Vec<String>,
+    // fmt only tells which files aren't formatted
+    pub check: bool,
+    // downloaded text has Unknown line with raw sysex bytes even when they are usual ones
+    pub show_unknown: bool,
+    // --lenient for firmware which sends pattern sysex slightly differently
+    pub decode: Decode,
 }
 
-pub const DEFAULT_PORTNAME: &'static str = "TD-3";
-const FILE: &'static str = "file";
-const IN: &'static str = "in";
-const OUT: &'static str = "out";
+impl Config {
+    // commands like upload and watch work with exactly one slot
+    pub fn slot(&self) -> Result<SlotAddress, Box<dyn Error>> {
+        match self.slots.as_slice() {
+            [slot] => Ok(*slot),
+            _ => Err(format!("{:?} works with single slot, {} slot(s) are given", self.mode, self.slots.len()).into()),
+        }
+    }
+}
+
+// device alias from [device name] section of config file
+#[derive(Debug, Default, Clone)]
+pub struct DeviceAlias {
+    pub in_port: Option<String>,
+    pub out_port: Option<String>,
+    pub model: Option<String>,
+}
+
+// optional config file, everything in it could be overriden by environment and command line
+#[derive(Debug, Default)]
+pub struct UserConfig {
+    pub device: DeviceAlias,
+    pub format: Option<Format>,
+    pub backup_dir: Option<String>,
+    pub devices: HashMap<String, DeviceAlias>,
+}
+
+pub const DEFAULT_PORTNAME: &str = "TD-3";
+// -file=- is stdin or stdout
+pub const STDIO: &str = "-";
+const FILE: &str = "file";
+const DIR: &str = "dir";
+const TRACK: &str = "track";
+const DRY_RUN: &str = "dry-run";
+const YES: &str = "yes";
+const ALLOW_UNCONFIRMED: &str = "allow-unconfirmed";
+const IN: &str = "in";
+const OUT: &str = "out";
+const MODEL: &str = "model";
+const DEVICE: &str = "device";
+const FORMAT: &str = "format";
+const BACKUP_DIR: &str = "backup-dir";
+const HELP: &str = "help";
+const QUIET: &str = "quiet";
+const CHECK: &str = "check";
+const SHOW_UNKNOWN: &str = "show-unknown";
+const LENIENT: &str = "lenient";
+
+const CONFIG_ENV: &str = "TD3_CONFIG";
+const IN_ENV: &str = "TD3_IN";
+const OUT_ENV: &str = "TD3_OUT";
+const MODEL_ENV: &str = "TD3_MODEL";
+const DEVICE_ENV: &str = "TD3_DEVICE";
+const FORMAT_ENV: &str = "TD3_FORMAT";
+const BACKUP_DIR_ENV: &str = "TD3_BACKUP_DIR";
+
+// set by --quiet, read by status! from everywhere
+static QUIET_FLAG: AtomicBool = AtomicBool::new(false);
+
+pub fn quiet() -> bool {
+    QUIET_FLAG.load(Ordering::Relaxed)
+}
+
+// $TD3_CONFIG, then $XDG_CONFIG_HOME/td3-pattern/config, ~/.config/td3-pattern/config or %APPDATA%\td3-pattern\config
+pub fn config_path() -> Option<PathBuf> {
+    if let Ok(path) = env::var(CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    let dir = match (env::var("XDG_CONFIG_HOME"), env::var("HOME"), env::var("APPDATA")) {
+        (Ok(xdg), _, _) if !xdg.is_empty() => PathBuf::from(xdg),
+        (_, Ok(home), _) => PathBuf::from(home).join(".config"),
+        (_, _, Ok(appdata)) => PathBuf::from(appdata),
+        _ => return None,
+    };
+    Some(dir.join("td3-pattern").join("config"))
+}
+
+fn parse_format(value: &str) -> Result<Format, Box<dyn Error>> {
+    match Format::from_str(value) {
+        Ok(format) => Ok(format),
+        _ => Err(format!("Format should be text or syx, read: {}", value).into()),
+    }
+}
+
+// key = value lines, [device name] starts alias section, // starts comment
+pub fn parse_user_config(content: &str) -> Result<UserConfig, Box<dyn Error>> {
+    let mut user_config: UserConfig = Default::default();
+    let mut section: Option<String> = None;
+    for (n, line) in content.lines().enumerate() {
+        let line = match line.find("//") {
+            Some(x) => line[0..x].trim(),
+            None => line.trim(),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            match line[1..line.len() - 1].trim().strip_prefix(DEVICE) {
+                Some(name) if !name.trim().is_empty() => {
+                    section = Some(name.trim().to_owned());
+                    user_config.devices.insert(name.trim().to_owned(), Default::default());
+                }
+                _ => return Err(format!("Config line {}: expecting [device name], read: {}", n + 1, line).into()),
+            }
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim().trim_matches('"').to_owned()),
+            None => return Err(format!("Config line {}: expecting key = value, read: {}", n + 1, line).into()),
+        };
+        let device = match &section {
+            Some(name) => user_config.devices.get_mut(name).unwrap(),
+            None => &mut user_config.device,
+        };
+        match key {
+            IN => device.in_port = Some(value),
+            OUT => device.out_port = Some(value),
+            MODEL => device.model = Some(value),
+            FORMAT if section.is_none() => user_config.format = Some(parse_format(&value)?),
+            BACKUP_DIR if section.is_none() => user_config.backup_dir = Some(value),
+            _ => return Err(format!("Config line {}: unknown key {}", n + 1, key).into()),
+        }
+    }
+    Ok(user_config)
+}
+
+pub fn read_user_config() -> Result<UserConfig, Box<dyn Error>> {
+    match config_path() {
+        Some(path) if path.exists() => match parse_user_config(&read_to_string(&path)?) {
+            Ok(user_config) => Ok(user_config),
+            Err(e) => Err(format!("{}: {}", path.display(), e).into()),
+        },
+        _ => Ok(Default::default()),
+    }
+}
+
+// config file, then TD3_DEVICE alias, then TD3_IN/TD3_OUT/TD3_MODEL, then --device alias,
+// var is environment lookup, -in/-out/-model are applied after
+fn device_settings(
+    user_config: &UserConfig,
+    var: &dyn Fn(&str) -> Option<String>,
+    device_arg: Option<&str>,
+) -> Result<DeviceAlias, Box<dyn Error>> {
+    let alias = |device: DeviceAlias, alias: &str| match user_config.devices.get(alias) {
+        Some(aliased) => Ok(DeviceAlias {
+            in_port: aliased.in_port.clone().or(device.in_port),
+            out_port: aliased.out_port.clone().or(device.out_port),
+            model: aliased.model.clone().or(device.model),
+        }),
+        None => Err(format!("Device {} is not found in config file", alias)),
+    };
+    let mut device = user_config.device.clone();
+    // command line alias replaces the one from environment
+    if let (None, Some(name)) = (device_arg, var(DEVICE_ENV)) {
+        device = alias(device, &name)?;
+    }
+    device = DeviceAlias {
+        in_port: var(IN_ENV).or(device.in_port),
+        out_port: var(OUT_ENV).or(device.out_port),
+        model: var(MODEL_ENV).or(device.model),
+    };
+    if let Some(name) = device_arg {
+        device = alias(device, name)?;
+    }
+    Ok(device)
+}
+
+fn apply_user_config(config: &mut Config, argv: &argmap::Map) -> Result<(), Box<dyn Error>> {
+    let user_config = read_user_config()?;
+    let device_arg = argv.get(DEVICE).and_then(|v| v.first()).map(|s| s.as_str());
+    let device = device_settings(&user_config, &|key| env::var(key).ok(), device_arg)?;
+    if let Some(in_port) = device.in_port {
+        config.in_port = in_port;
+    }
+    if let Some(out_port) = device.out_port {
+        config.out_port = out_port;
+    }
+    config.model = device.model;
+    config.format = match env::var(FORMAT_ENV) {
+        Ok(format) => parse_format(&format)?,
+        Err(_) => user_config.format.unwrap_or(Format::Text),
+    };
+    if let Some(dir) = env::var(BACKUP_DIR_ENV).ok().or(user_config.backup_dir) {
+        config.dir = dir;
+    }
+    Ok(())
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> (Vec<String>, argmap::Map) {
+    argmap::new().booleans(&[DRY_RUN, YES, ALLOW_UNCONFIRMED, HELP, QUIET, CHECK, SHOW_UNKNOWN, LENIENT]).parse(args)
+}
+
+// no command, help command or --help, args start with the program name
+fn wants_help(args: &[String], argv: &argmap::Map) -> bool {
+    argv.contains_key(HELP) || args.get(1).is_none_or(|command| matches!(Mode::from_str(command), Ok(Mode::Help)))
+}
+
+// value of -key=value, empty value is the same as missing key
+fn arg_value(argv: &argmap::Map, key: &str) -> Option<String> {
+    match argv.get(key).and_then(|v| v.first()) {
+        Some(value) if !value.is_empty() => Some(value.to_owned()),
+        _ => None,
+    }
+}
 
 pub fn get_config() -> Result<Config, Box<dyn Error>> {
     let mut config = Config {
@@ -50,75 +366,230 @@ pub fn get_config() -> Result<Config, Box<dyn Error>> {
         out_port: DEFAULT_PORTNAME.to_owned(),
         mode: Mode::Download,
         filename: String::from(""),
-        group: 0,
-        pnum: 0,
-        ab: 0,
+        dir: String::from(""),
+        direction: Direction::Pull,
+        dry_run: false,
+        yes: false,
+        allow_unconfirmed: false,
+        slots: Vec::new(),
+        track: None,
+        model: None,
+        settings_action: SettingsAction::Show,
+        settings: Vec::new(),
+        format: Format::Text,
+        help: None,
+        paths: Vec::new(),
+        check: false,
+        show_unknown: false,
+        decode: Decode::Strict,
     };
-    let (args, argv) = argmap::parse(std::env::args());
-
-    // Main args -- [verb] grpoup
-    if args.len() < 2 {
-        return Err("Incorrect number of arguments".into());
-    }
-    let mut iter = args.iter();
-    let _ = iter.next();
-    let mut arg = iter.next().unwrap();
-    match Mode::from_str(arg) {
-        Ok(Mode::Upload) => {
-            config.mode = Mode::Upload;
-            if args.len() < 4 {
-                return Err("Invalid number of program arguments for upload".into());
+    let (args, argv) = parse_args(std::env::args());
+    // a broken config file must not hide the help that explains it
+    if let Err(e) = apply_user_config(&mut config, &argv) {
+        if !wants_help(&args, &argv) {
+            return Err(e);
+        }
+        eprintln!("Warning: {}", e);
+    }
+    QUIET_FLAG.store(argv.contains_key(QUIET), Ordering::Relaxed);
+
+    // command, then its arguments, td3pattern <group> <pattern> and td3pattern track <track> are downloads
+    let mut args = args.iter().skip(1).map(|a| a.as_str()).collect::<Vec<&str>>();
+    if args.is_empty() {
+        config.mode = Mode::Help;
+        return Ok(config);
+    }
+    let command = args[0];
+    match Mode::from_str(command) {
+        Ok(mode) => {
+            config.mode = mode;
+            args.remove(0);
+        }
+        Err(_) if command != TRACK && parse_group(command).is_err() && parse_slots(command).is_err() => {
+            return Err(format!("Unknown command {}", command).into());
+        }
+        Err(_) => (),
+    }
+    if argv.contains_key(HELP) {
+        config.help = Some(command.to_owned()).filter(|_| !matches!(config.mode, Mode::Help));
+        config.mode = Mode::Help;
+        return Ok(config);
+    }
+    config.filename = arg_value(&argv, FILE).unwrap_or_default();
+    config.dry_run = argv.contains_key(DRY_RUN);
+    config.yes = argv.contains_key(YES);
+    config.allow_unconfirmed = argv.contains_key(ALLOW_UNCONFIRMED);
+    config.show_unknown = argv.contains_key(SHOW_UNKNOWN);
+    if argv.contains_key(LENIENT) {
+        config.decode = Decode::Lenient;
+    }
+    if let Some(dir) = arg_value(&argv, DIR) {
+        config.dir = dir;
+    }
+    match config.mode {
+        Mode::Help => config.help = args.first().map(|a| a.to_string()),
+        Mode::Download | Mode::Upload | Mode::Watch => {
+            if args.first() == Some(&TRACK) {
+                match args.get(1) {
+                    Some(track) if args.len() == 2 => config.track = Some(parse_track(track)?),
+                    _ => return Err("Expecting single track number after track".into()),
+                }
+            } else {
+                config.slots = parse_slot_args(&args)?;
+            }
+            // bank file knows its slots
+            if config.slots.is_empty() && config.track.is_none() && !matches!(config.mode, Mode::Upload) {
+                return Err(format!("{:?} needs a slot, e.g. 2:3B", config.mode).into());
             }
-            if !argv.contains_key(FILE) || argv.get(FILE).unwrap().first().unwrap() == "" {
-                return Err("For upload -file=\"filename\" should be specified".into());
+            if !matches!(config.mode, Mode::Download) {
+                if config.filename.is_empty() {
+                    return Err(format!("For {} -file=\"filename\" should be specified", command).into());
+                }
+                // bank file could be uploaded to several slots, it's known only after file is read
+                if let (Mode::Watch, None) = (&config.mode, config.track) {
+                    config.slot()?;
+                }
+                // watch needs a file to poll
+                if let (Mode::Watch, STDIO) = (&config.mode, config.filename.as_str()) {
+                    return Err("Watch can't read from stdin, -file=\"filename\" should be specified".into());
+                }
             }
-            arg = iter.next().unwrap();
         }
-        _ => {
-            if args.len() < 3 {
-                return Err("Invalid number of program arguments for download/print".into());
+        Mode::Backup | Mode::Restore | Mode::Sync => {
+            if let Mode::Sync = config.mode {
+                config.direction = match args.first().map(|a| Direction::from_str(a)) {
+                    Some(Ok(direction)) => direction,
+                    _ => return Err("Sync direction should be pull or push".into()),
+                };
+                args.remove(0);
+            } else if let Mode::Restore = config.mode {
+                config.direction = Direction::Push;
+            }
+            // everything by default
+            config.slots = if args.is_empty() { all_slots().collect() } else { parse_slot_args(&args)? };
+            if config.dir.is_empty() {
+                return Err(format!(
+                    "For {} -dir=\"directory\" or backup-dir in config file should be specified",
+                    command
+                )
+                .into());
+            }
+        }
+        Mode::Settings => {
+            config.settings_action = match args.first().map(|a| SettingsAction::from_str(a)) {
+                Some(Ok(action)) => action,
+                _ => return Err("Settings action should be show or set".into()),
+            };
+            config.settings = args[1..].iter().map(|a| a.to_string()).collect();
+            if let SettingsAction::Set = config.settings_action {
+                if config.settings.is_empty() && config.filename.is_empty() {
+                    return Err("For settings set setting=value or -file=\"filename\" should be specified".into());
+                }
+            }
+        }
+        Mode::Fmt | Mode::Lint => {
+            config.paths = args.iter().map(|a| a.to_string()).collect();
+            config.check = argv.contains_key(CHECK);
+            if config.paths.is_empty() {
+                return Err(format!("{:?} needs files or directories, - for stdin", config.mode).into());
+            }
+        }
+        Mode::List | Mode::Info => {
+            if !args.is_empty() {
+                return Err(format!("{} doesn't take arguments, read: {}", command, args.join(" ")).into());
             }
         }
     }
-    // group
-    config.group = match arg.parse::<u8>() {
-        Ok(group) => match group {
-            1..=4 => group - 1,
-            _ => return Err("Group should be from 1 to 4".into()),
-        },
-        _ => return Err(format!("Group {:?} is invalid", arg.parse::<u8>()).into()),
-    };
-    // pattern number|ab
-    let arg = iter.next().unwrap();
-    if arg.len() != 2 {
-        return Err("Pattern should consist of number from 1 to 8 and letter A or B".into());
-    }
-    config.pnum = match arg[0..1].parse::<u8>() {
-        Ok(pattern) => match pattern {
-            1..=8 => pattern - 1,
-            _ => return Err("Pattern should start with number from 1 to 8".into()),
-        },
-        _ => return Err("Pattern should start with number".into()),
-    };
-    config.ab = match &arg[1..2] {
-        "A" | "a" => 0,
-        "B" | "b" => 1,
-        _ => return Err("Pattern should end with letter A or B".into()),
-    };
-    // -filename
-    if argv.contains_key(FILE) {
-        config.filename = argv.get(FILE).unwrap().first().unwrap().to_string();
+    if let Some(in_port) = arg_value(&argv, IN) {
+        config.in_port = in_port;
     }
-    // -in_port
-    if argv.contains_key(IN) && argv.get(IN).unwrap().first().unwrap() != "" {
-        config.in_port = argv.get(IN).unwrap().first().unwrap().to_string();
+    if let Some(out_port) = arg_value(&argv, OUT) {
+        config.out_port = out_port;
     }
-    // -out_port
-    if argv.contains_key(OUT) && argv.get(OUT).unwrap().first().unwrap() != "" {
-        config.out_port = argv.get(OUT).unwrap().first().unwrap().to_string();
+    if let Some(model) = arg_value(&argv, MODEL) {
+        config.model = Some(model);
+    }
+    if let Some(format) = arg_value(&argv, FORMAT) {
+        config.format = parse_format(&format)?;
     }
     if cfg!(debug_assertions) {
-        println!("|| config={:?}", config);
+        eprintln!("|| config={:?}", config);
     }
     Ok(config)
 }
+
+// track
+fn parse_track(arg: &str) -> Result<u8, Box<dyn Error>> {
+    match arg.parse::<u8>() {
+        Ok(track) if (1..=TRACKS).contains(&track) => Ok(track - 1),
+        _ => Err(format!("Track should be from 1 to {}", TRACKS).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+// default ports
+in = TD-3 MIDI 1
+out = \"TD-3 MIDI 1\"
+format = syx
+backup-dir = /tmp/td3  // comment
+
+[device right]
+in = TD-3-MO
+model = TD-3-MO
+";
+
+    #[test]
+    fn user_config_is_parsed() {
+        let user_config = parse_user_config(CONFIG).unwrap();
+        assert_eq!(user_config.device.in_port.as_deref(), Some("TD-3 MIDI 1"));
+        assert_eq!(user_config.device.out_port.as_deref(), Some("TD-3 MIDI 1"));
+        assert!(matches!(user_config.format, Some(Format::Sysex)));
+        assert_eq!(user_config.backup_dir.as_deref(), Some("/tmp/td3"));
+        let right = &user_config.devices["right"];
+        assert_eq!((right.in_port.as_deref(), right.out_port.as_deref()), (Some("TD-3-MO"), None));
+        assert_eq!(right.model.as_deref(), Some("TD-3-MO"));
+        for wrong in ["in TD-3", "tempo = 120", "format = wav", "[left]", "[device]", "[device x]\nformat = syx"] {
+            assert!(parse_user_config(wrong).is_err(), "{}", wrong);
+        }
+    }
+
+    fn device(env: &[(&str, &str)], device_arg: Option<&str>) -> Result<DeviceAlias, Box<dyn Error>> {
+        let user_config = parse_user_config(CONFIG).unwrap();
+        let var = |key: &str| env.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string());
+        device_settings(&user_config, &var, device_arg)
+    }
+
+    #[test]
+    fn device_settings_precedence() {
+        let ports = |d: DeviceAlias| (d.in_port.unwrap(), d.out_port.unwrap(), d.model);
+        let file = (String::from("TD-3 MIDI 1"), String::from("TD-3 MIDI 1"), None);
+        assert_eq!(ports(device(&[], None).unwrap()), file);
+        // environment overrides config file and TD3_DEVICE alias
+        let env_in = device(&[(IN_ENV, "env"), (DEVICE_ENV, "right")], None).unwrap();
+        assert_eq!(ports(env_in), (String::from("env"), file.1.clone(), Some(String::from("TD-3-MO"))));
+        // --device overrides environment, what alias doesn't set comes from environment
+        let arg = device(&[(IN_ENV, "env"), (OUT_ENV, "env out"), (MODEL_ENV, "TD-3")], Some("right")).unwrap();
+        assert_eq!(ports(arg), (String::from("TD-3-MO"), String::from("env out"), Some(String::from("TD-3-MO"))));
+        assert!(device(&[(DEVICE_ENV, "left")], None).is_err());
+        assert!(device(&[(DEVICE_ENV, "left")], Some("right")).is_ok());
+        assert!(device(&[], Some("left")).is_err());
+    }
+
+    #[test]
+    fn help_is_found_before_config() {
+        let help = |line: &str| {
+            let (args, argv) = parse_args(line.split(' ').map(String::from));
+            wants_help(&args, &argv)
+        };
+        assert!(help("td3-pattern"));
+        assert!(help("td3-pattern help"));
+        assert!(help("td3-pattern help sync"));
+        assert!(help("td3-pattern sync --help"));
+        assert!(!help("td3-pattern sync"));
+        assert!(!help("td3-pattern 1 A"));
+    }
+}
@@ -0,0 +1,162 @@
+use std::error::Error;
+
+use crate::firmware::Version;
+
+// everything which could differ between members of TD-3 family
+pub struct Profile {
+    // product name as device reports it
+    pub model: &'static str,
+    // lowest firmware version profile applies to, profiles are keyed by model and firmware
+    pub min_firmware: &'static [u8],
+    // default midi port name, matched as part of name or regex
+    pub port_name: &'static str,
+    // F0, Behringer manufacturer id, device id
+    pub header: &'static [u8],
+    // pattern response size without header and F7
+    pub pattern_len: usize,
+}
+
+// sysex framing doesn't need midi, files and fixtures are checked the same way as device responses
+pub const SYX_POST: &[u8] = &[0xF7];
+
+const TD3_HEADER: &[u8] = &[0xF0, 0x00, 0x20, 0x32, 0x00, 0x01, 0x0A];
+const TD3_PATTERN_LEN: usize = 0x73;
+
+// first profile is used when model is not known yet, e.g. for product name request;
+// MO, SR and AM values are copied from TD-3, they are placeholders until somebody checks them on real devices,
+// firmware which changes any of them gets its own entry with min_firmware
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        model: "TD-3",
+        min_firmware: &[],
+        port_name: "TD-3",
+        header: TD3_HEADER,
+        pattern_len: TD3_PATTERN_LEN,
+    },
+    Profile {
+        model: "TD-3-MO",
+        min_firmware: &[],
+        port_name: "TD-3-MO",
+        header: TD3_HEADER,
+        pattern_len: TD3_PATTERN_LEN,
+    },
+    Profile {
+        model: "TD-3-SR",
+        min_firmware: &[],
+        port_name: "TD-3-SR",
+        header: TD3_HEADER,
+        pattern_len: TD3_PATTERN_LEN,
+    },
+    Profile {
+        model: "TD-3-AM",
+        min_firmware: &[],
+        port_name: "TD-3-AM",
+        header: TD3_HEADER,
+        pattern_len: TD3_PATTERN_LEN,
+    },
+];
+
+pub fn default_profile() -> &'static Profile {
+    &PROFILES[0]
+}
+
+pub fn known_models() -> String {
+    let mut models = PROFILES.iter().map(|p| p.model).collect::<Vec<&str>>();
+    models.dedup();
+    models.join(", ")
+}
+
+// most specific profile for model and firmware, i.e. with highest min_firmware which is not above firmware
+pub fn find_profile(model: &str, firmware: &Version) -> Option<&'static Profile> {
+    select_profile(PROFILES, model, firmware)
+}
+
+fn select_profile<'a>(profiles: &'a [Profile], model: &str, firmware: &Version) -> Option<&'a Profile> {
+    profiles
+        .iter()
+        .filter(|p| p.model == model && p.min_firmware <= firmware.parts())
+        .max_by(|a, b| a.min_firmware.cmp(b.min_firmware))
+}
+
+// -model=name makes unknown device to be treated as one of known models, firmware isn't known before
+// the first exchange, so it's the profile of the oldest firmware
+pub fn profile_override(model: &str) -> Result<&'static Profile, Box<dyn Error>> {
+    match PROFILES.iter().filter(|p| p.model == model).min_by(|a, b| a.min_firmware.cmp(b.min_firmware)) {
+        Some(profile) => Ok(profile),
+        None => Err(format!("Unknown model {}, known models are: {}", model, known_models()).into()),
+    }
+}
+
+// complete sysex message for data part, e.g. for saving to .syx file
+pub fn wrap_sysex(profile: &Profile, data_smsg: &[u8]) -> Vec<u8> {
+    let mut msg = profile.header.to_vec();
+    msg.extend_from_slice(data_smsg);
+    msg.extend_from_slice(SYX_POST);
+    msg
+}
+
+// messages of .syx file, several downloaded patterns just follow each other, each ends with F7
+pub fn split_sysex(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|b| *b == SYX_POST[0]).collect()
+}
+
+// matches head and tail and returns data part of message
+pub fn check_response(m: &[u8], header: &[u8], desc: &str) -> Result<std::vec::Vec<u8>, Box<dyn Error>> {
+    if m.len() < header.len() + SYX_POST.len() + 1
+        || header[0..header.len()].iter().zip(&m[0..header.len()]).filter(|&(a, b)| a != b).count() > 0
+        || *m.last().unwrap() != SYX_POST[0]
+    {
+        Err(format!("Response for {} has wrong size", desc).into())
+    } else {
+        Ok(m[header.len()..m.len() - 1].to_owned())
+    }
+}
+
+pub fn parse_product_name(msg: &[u8]) -> Result<String, Box<dyn Error>> {
+    if msg.len() < 2 {
+        return Err("Response for product name is too short".into());
+    }
+    // could also be .trim_matches(char::from(0)) for cutting traling 0
+    Ok(std::str::from_utf8(&msg[1..msg.len() - 1])?.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER_V2: &[u8] = &[0xF0, 0x00, 0x20, 0x32, 0x00, 0x01, 0x0B];
+
+    #[test]
+    fn profile_is_picked_by_model_and_firmware() {
+        let profile = |model, min_firmware, header| Profile {
+            model,
+            min_firmware,
+            port_name: model,
+            header,
+            pattern_len: TD3_PATTERN_LEN,
+        };
+        let profiles = [
+            profile("TD-3", &[], TD3_HEADER),
+            profile("TD-3", &[1, 10], HEADER_V2),
+            profile("TD-3-MO", &[1, 2], TD3_HEADER),
+        ];
+        let header = |model, version: &[u8]| select_profile(&profiles, model, &Version::new(version)).map(|p| p.header);
+        assert_eq!(header("TD-3", &[1, 3, 7]), Some(TD3_HEADER));
+        assert_eq!(header("TD-3", &[1, 10]), Some(HEADER_V2));
+        assert_eq!(header("TD-3", &[1, 10, 1]), Some(HEADER_V2));
+        assert_eq!(header("TD-3-MO", &[1, 1]), None);
+        assert_eq!(header("TD-3-MO", &[1, 2, 0]), Some(TD3_HEADER));
+        assert_eq!(header("TD-3-XX", &[1, 2]), None);
+        assert_eq!(find_profile("TD-3-SR", &Version::new(&[1, 3, 7])).map(|p| p.model), Some("TD-3-SR"));
+        assert!(profile_override("TD-3-AM").is_ok() && profile_override("TB-303").is_err());
+    }
+
+    #[test]
+    fn sysex_file_is_split_into_messages() {
+        let data = [0xF0, 0x01, 0xF7, 0xF0, 0x02, 0x03, 0xF7];
+        assert_eq!(split_sysex(&data), vec![&data[0..3], &data[3..7]]);
+        assert_eq!(split_sysex(&data[0..3]), vec![&data[0..3]]);
+        // truncated message is kept, check_response rejects it
+        assert_eq!(split_sysex(&data[0..5]), vec![&data[0..3], &data[3..5]]);
+    }
+}
@@ -0,0 +1,293 @@
+// commands which talk to device, only built with midi feature
+use std::error::Error;
+use std::sync::mpsc;
+
+use td3_pattern::bank::{bank_to_string, file_to_bank, is_bank, slot_header, BankEntry};
+use td3_pattern::config::{Config, Direction, Format, Mode, SettingsAction, STDIO};
+use td3_pattern::device::{
+    check_response, default_profile, find_profile, known_models, parse_product_name, profile_override, split_sysex,
+    wrap_sysex, Profile,
+};
+use td3_pattern::firmware::{check_version, require, require_write, support, Feature, Version, FEATURES};
+use td3_pattern::metadata::{download_metadata, remember_metadata};
+use td3_pattern::midicomm::{get_pattern, get_track, open_ports, port_names, probe_ports, send_sysex};
+use td3_pattern::pattern::{
+    decode_pattern, file_to_pattern_with_metadata, pattern_to_string_with_unknown, pattern_to_sysex,
+};
+use td3_pattern::settings::{get_settings, parse_assignment, set_setting, settings_to_string, string_to_settings};
+use td3_pattern::slot::SlotAddress;
+use td3_pattern::status;
+use td3_pattern::sync::sync;
+use td3_pattern::track::{string_to_track, sysex_to_track, track_to_string, track_to_sysex};
+use td3_pattern::watch::watch;
+
+use crate::{is_stdio, read_input, write_output};
+
+pub fn list() -> Result<(), Box<dyn Error>> {
+    let (out_names, in_names) = port_names()?;
+    let mut list = String::from("Midi out ports:\n");
+    for name in out_names.iter() {
+        list.push_str(&format!("    {}\n", name));
+    }
+    list.push_str("Midi in ports:\n");
+    for name in in_names.iter() {
+        list.push_str(&format!("    {}\n", name));
+    }
+    let devices = probe_ports()?;
+    if devices.is_empty() {
+        list.push_str("No devices have answered\n");
+    }
+    for device in devices.iter() {
+        list.push_str(&format!("{}: -in=\"{}\" -out=\"{}\"\n", device.product_name, device.in_port, device.out_port));
+    }
+    write_output(STDIO, list.as_bytes())
+}
+
+pub fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+    let model = config.model.as_deref().map(profile_override).transpose()?;
+    let (out_midi, out_port, in_midi, in_port) = open_ports(&(config.out_port), &(config.in_port), model)?;
+    let (tx, rx) = mpsc::channel::<Vec<u8>>();
+    // midi receive thread is opened by midir
+    let _in_connection = in_midi.connect(
+        &in_port,
+        "midir-read-input",
+        move |_stamp, msg, _| {
+            let _x = tx.send(msg.to_owned());
+        },
+        (),
+    );
+    // main/midi send
+    let mut out_conn = out_midi.connect(&out_port, "").map_err(|e| format!("Output port is not opened: {}", e))?;
+    match main_processor(&mut out_conn, rx, config) {
+        Ok(_) => std::process::exit(0),
+        Err(e) => {
+            eprintln!("Error has occured: {}", e);
+            std::process::exit(1);
+        }
+    };
+    /* loop {
+        std::thread::yield_now();
+    } */
+}
+
+fn main_processor(
+    out_conn: &mut midir::MidiOutputConnection,
+    rx: std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    // model isn't known yet, so TD-3 header is used unless -model says otherwise
+    let first_profile = match &config.model {
+        Some(model) => profile_override(model)?,
+        None => default_profile(),
+    };
+    let product_name_sysex = send_sysex(out_conn, &rx, first_profile, "product name", &[0x06])?;
+    let product_name = parse_product_name(&product_name_sysex)?;
+    let fw_version_sysex = send_sysex(out_conn, &rx, first_profile, "firmware version", &[0x08, 0x00])?;
+    let fw_version = Version::from_sysex(&fw_version_sysex)?;
+    let model = config.model.as_deref().unwrap_or(&product_name);
+    let profile = match find_profile(model, &fw_version) {
+        Some(profile) => profile,
+        None if config.model.is_some() => {
+            return Err(format!("There is no {} profile for firmware {}", model, fw_version).into())
+        }
+        None => {
+            return Err(format!(
+                "Product name is: '{}', known models are: {}, use -model=name to treat it as one of them",
+                product_name,
+                known_models()
+            )
+            .into())
+        }
+    };
+    status!("Product Name {}, Firmware version is {}", product_name, fw_version);
+    if let Some(warning) = check_version(&fw_version)? {
+        eprintln!("{}", warning);
+    }
+    let feature = match config.mode {
+        _ if config.track.is_some() => Some(Feature::Track),
+        Mode::Sync if matches!(config.direction, Direction::Push) => Some(Feature::PatternUpload),
+        Mode::Download | Mode::Backup | Mode::Sync => Some(Feature::PatternDownload),
+        Mode::Upload | Mode::Watch | Mode::Restore => Some(Feature::PatternUpload),
+        Mode::Settings => Some(Feature::Settings),
+        Mode::List | Mode::Info | Mode::Help | Mode::Fmt | Mode::Lint => None,
+    };
+    let writes = match config.mode {
+        Mode::Upload => true,
+        Mode::Settings => matches!(config.settings_action, SettingsAction::Set),
+        _ => false,
+    };
+    let required = match feature {
+        Some(f) if writes => require_write(&fw_version, f, config.allow_unconfirmed)?,
+        Some(f) => require(&fw_version, f)?,
+        None => None,
+    };
+    if let Some(warning) = required {
+        eprintln!("{}", warning);
+    }
+
+    if let Some(tnum) = config.track {
+        return track_processor(out_conn, &rx, profile, config, tnum);
+    }
+    match config.mode {
+        Mode::Download => {
+            // several patterns are written as bank file, sysex messages just follow each other and upload splits them
+            let mut data = Vec::new();
+            let mut entries = Vec::new();
+            for &slot in config.slots.iter() {
+                let pattern_sysex = get_pattern(out_conn, &rx, profile, slot)?;
+                let pattern = decode_pattern(&pattern_sysex, Some(slot), config.decode)?;
+                match config.format {
+                    Format::Sysex => data.extend(wrap_sysex(profile, &pattern_sysex)),
+                    Format::Text => {
+                        let metadata = download_metadata(&pattern, slot);
+                        entries.push(BankEntry { slot, pattern, metadata });
+                    }
+                }
+                status!("{}", slot_name(slot));
+            }
+            match entries.as_slice() {
+                [] => (),
+                [entry] => {
+                    let text = pattern_to_string_with_unknown(&entry.pattern, &entry.metadata, config.show_unknown);
+                    data = text.into_bytes()
+                }
+                _ => data = bank_to_string(&entries, config.show_unknown).into_bytes(),
+            }
+            write_output(&config.filename, &data)?;
+            if !is_stdio(&config.filename) {
+                status!("{} pattern(s) are saved to {}", config.slots.len(), config.filename);
+            }
+        }
+        Mode::Upload => {
+            let content = read_input(&config.filename)?;
+            let name = if is_stdio(&config.filename) { "<stdin>" } else { config.filename.as_str() };
+            // sysex file is recognized by F0 in the beginning, single pattern could be saved from any slot,
+            // several patterns go back to slots they were downloaded from
+            let entries = if content.first() == Some(&0xF0) {
+                let messages = split_sysex(&content);
+                let mut entries = Vec::new();
+                for message in messages.iter() {
+                    let msg = check_response(message, profile.header, "pattern file")?;
+                    let pattern = match decode_pattern(&msg, None, config.decode) {
+                        Ok(pattern) => pattern,
+                        Err(e) => {
+                            return Err(format!("File {} is not TD-3 pattern sysex: {}", config.filename, e).into())
+                        }
+                    };
+                    let slot = match messages.len() {
+                        1 => config.slot()?,
+                        _ => SlotAddress::from_sysex(msg[1], msg[2])?,
+                    };
+                    entries.push(BankEntry { slot, pattern, metadata: Default::default() });
+                }
+                match messages.len() {
+                    1 => entries,
+                    _ => pick_slots(entries, &config.slots, &config.filename)?,
+                }
+            } else if is_bank(&String::from_utf8_lossy(&content)) {
+                let bank = file_to_bank(name, String::from_utf8(content)?)?;
+                pick_slots(bank, &config.slots, &config.filename)?
+            } else {
+                let (pattern, metadata) = file_to_pattern_with_metadata(name, String::from_utf8(content)?)?;
+                vec![BankEntry { slot: config.slot()?, pattern, metadata }]
+            };
+            for BankEntry { slot, pattern, metadata } in entries.iter() {
+                let pattern_sysex = pattern_to_sysex(pattern, *slot);
+                send_sysex(out_conn, &rx, profile, "pattern", pattern_sysex.as_slice())?;
+                remember_metadata(pattern, metadata, *slot);
+                status!("File {} is uploaded to {}", config.filename, slot_name(*slot));
+            }
+        }
+        Mode::Backup | Mode::Restore | Mode::Sync => sync(out_conn, &rx, profile, config)?,
+        Mode::Watch => watch(out_conn, &rx, profile, &config.filename, config.slot()?)?,
+        Mode::Settings => settings_processor(out_conn, &rx, profile, config)?,
+        Mode::Info => {
+            let mut info = format!("Model: {}\nFirmware: {}\nCapabilities:\n", profile.model, fw_version);
+            for feature in FEATURES.iter() {
+                info.push_str(&format!("    {}: {}\n", feature, support(&fw_version, *feature)));
+            }
+            write_output(STDIO, info.as_bytes())?;
+        }
+        // ports aren't opened for list and help
+        Mode::List | Mode::Help | Mode::Fmt | Mode::Lint => (),
+    }
+    Ok(())
+}
+
+// slots pick patterns from bank or sysex file, whole file is uploaded if there are none
+fn pick_slots(
+    entries: Vec<BankEntry>,
+    slots: &[SlotAddress],
+    filename: &str,
+) -> Result<Vec<BankEntry>, Box<dyn Error>> {
+    if let Some(slot) = slots.iter().find(|slot| !entries.iter().any(|entry| entry.slot == **slot)) {
+        return Err(format!("{} is not found in {}", slot_header(*slot), filename).into());
+    }
+    Ok(entries.into_iter().filter(|entry| slots.is_empty() || slots.contains(&entry.slot)).collect())
+}
+
+fn slot_name(slot: SlotAddress) -> String {
+    format!("Group {} Pattern: {}{}", slot.group() + 1, slot.number() + 1, slot.side())
+}
+
+fn track_processor(
+    out_conn: &mut midir::MidiOutputConnection,
+    rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    profile: &Profile,
+    config: &Config,
+    tnum: u8,
+) -> Result<(), Box<dyn Error>> {
+    match config.mode {
+        Mode::Download => {
+            let track_sysex = get_track(out_conn, rx, profile, tnum)?;
+            let track = sysex_to_track(&track_sysex, Some(tnum))?;
+            let string_track = track_to_string(&track);
+            write_output(&config.filename, string_track.as_bytes())?;
+            match is_stdio(&config.filename) {
+                true => status!("Track: {}", tnum + 1),
+                false => status!("Track {} is saved to {}", tnum + 1, config.filename),
+            }
+        }
+        Mode::Upload => {
+            let string_track = String::from_utf8(read_input(&config.filename)?)?;
+            let track = string_to_track(string_track)?;
+            let track_sysex = track_to_sysex(&track, tnum)?;
+            send_sysex(out_conn, rx, profile, "track", track_sysex.as_slice())?;
+            status!("File {} is uploaded to Track {}", config.filename, tnum + 1);
+        }
+        _ => return Err(format!("{:?} is not supported for tracks", config.mode).into()),
+    }
+    Ok(())
+}
+
+fn settings_processor(
+    out_conn: &mut midir::MidiOutputConnection,
+    rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    profile: &Profile,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    match config.settings_action {
+        SettingsAction::Show => {
+            let string_settings = settings_to_string(&get_settings(out_conn, rx, profile)?);
+            write_output(&config.filename, string_settings.as_bytes())?;
+            if !is_stdio(&config.filename) {
+                status!("Settings are saved to {}", config.filename);
+            }
+        }
+        SettingsAction::Set => {
+            // file is applied first, so settings from command line take priority
+            let mut values = Vec::new();
+            if !config.filename.is_empty() {
+                values.extend(string_to_settings(String::from_utf8(read_input(&config.filename)?)?)?);
+            }
+            for assignment in config.settings.iter() {
+                values.push(parse_assignment(assignment)?);
+            }
+            for (setting, raw) in values.iter() {
+                set_setting(out_conn, rx, profile, setting, *raw)?;
+                status!("{} is set to {}", setting.name, setting.format_value(*raw));
+            }
+        }
+    }
+    Ok(())
+}
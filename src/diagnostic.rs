@@ -0,0 +1,172 @@
+use std::error::Error;
+use std::fmt;
+
+// single problem in a text file, line and column are 1-based, line 0 is the file as a whole
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    // number of characters to underline
+    pub width: usize,
+    pub message: String,
+    // line as it is in the file, shown with caret under the problem
+    pub source: String,
+}
+
+// every problem found in the file, parser doesn't stop on the first one
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    pub file: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn push(&mut self, line: usize, column: usize, width: usize, message: String, source: &str) {
+        self.diagnostics.push(Diagnostic { line, column, width, message, source: source.to_owned() });
+    }
+
+    // problem which isn't tied to any line, e.g. missing line
+    pub fn push_file(&mut self, message: String) {
+        self.push(0, 0, 0, message, "");
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            return write!(f, "{}", self.message);
+        }
+        writeln!(f, "{}:{}: {}", self.line, self.column, self.message)?;
+        writeln!(f, "    {}", self.source)?;
+        // tabs are kept so caret lines up with the source line
+        let pad = self.source.chars().take(self.column - 1).map(|c| if c == '\t' { '\t' } else { ' ' });
+        write!(f, "    {}{}", pad.collect::<String>(), "^".repeat(self.width.max(1)))
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (n, diagnostic) in self.diagnostics.iter().enumerate() {
+            if n > 0 {
+                writeln!(f)?;
+            }
+            if let Some(file) = &self.file {
+                write!(f, "{}:", file)?;
+                if diagnostic.line == 0 {
+                    write!(f, " ")?;
+                }
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Diagnostics {}
+
+// Levenshtein distance, case insensitive
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.to_lowercase().chars().collect::<Vec<char>>();
+    let b = b.to_lowercase().chars().collect::<Vec<char>>();
+    let mut row = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.iter().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == cb { prev } else { 1 + prev.min(row[j]).min(row[j + 1]) };
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+// nearest candidate, if it's close enough to be what was meant, on a tie the one starting with input wins
+pub fn suggest<'a>(input: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let input = input.trim();
+    let lowercase = input.to_lowercase();
+    let (distance, _, candidate) = candidates
+        .iter()
+        .map(|c| (edit_distance(input, c), !c.to_lowercase().starts_with(&lowercase), *c))
+        .min_by_key(|(d, prefix, _)| (*d, *prefix))?;
+    match distance < input.chars().count() && distance <= 2 {
+        true => Some(candidate),
+        false => None,
+    }
+}
+
+// ", did you mean X?" or nothing
+pub fn did_you_mean(input: &str, candidates: &[&str]) -> String {
+    match suggest(input, candidates) {
+        Some(candidate) => format!(", did you mean {}?", candidate),
+        None => String::from(""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::file_to_bank;
+    use crate::pattern::file_to_pattern_with_metadata;
+
+    // one wrong value on line 2 and one on line 8, which starts with tab
+    const PATTERN: &str = "TD-3 Pattern
+Active Steps: 16, Triplet Time: Of
+
+Note:       D#, D#, C#, C#, C#, G#, D#, D#, G#, D#, E , D#, D#, C#, D#, G#
+Transpose:    ,   , DN,   , DN, UP, UP, UP, UP, UP,   , UP,   , UP, DN, UP
+Accent:       , AC,   ,   ,   ,   ,   ,   ,   ,   ,   ,   ,   ,   ,   ,
+Slide:        , SL,   ,   ,   ,   ,   , SL,   ,   ,   ,   ,   ,   ,   ,
+Tie/Rest:\t, TI,   ,   ,   , TI, TX,   ,   , TI, TI,   ,   , TR, TI,
+";
+
+    fn rendered(filename: &str, line: usize) -> String {
+        [
+            format!("{}:{}:33: Triplet Time should be On or Off, read: Of, did you mean Off?", filename, line),
+            String::from("    Active Steps: 16, Triplet Time: Of"),
+            String::from("                                    ^^"),
+            format!("{}:{}:33: Wrong 'Tie/Rest' on step 7: TX, did you mean TI?", filename, line + 6),
+            String::from("    Tie/Rest:\t, TI,   ,   ,   , TI, TX,   ,   , TI, TI,   ,   , TR, TI,"),
+            String::from("             \t                      ^^"),
+        ]
+        .join("\n")
+    }
+
+    #[test]
+    fn every_error_is_shown_with_caret() {
+        let error = file_to_pattern_with_metadata("a.txt", PATTERN.to_owned()).unwrap_err();
+        assert_eq!(error.to_string(), rendered("a.txt", 2));
+    }
+
+    #[test]
+    fn bank_errors_have_bank_lines() {
+        let bank = format!("// bank\n[Group 1 Pattern 1A]\n{}", PATTERN);
+        let error = file_to_bank("b.txt", bank).err().unwrap();
+        assert_eq!(error.to_string(), rendered("b.txt", 4));
+    }
+
+    #[test]
+    fn file_problems_have_no_line() {
+        let mut diagnostics = Diagnostics { file: Some(String::from("c.txt")), ..Default::default() };
+        diagnostics.push_file(String::from("Note line is missing"));
+        diagnostics.push(3, 1, 0, String::from("Wrong line"), "x");
+        assert_eq!(diagnostics.to_string(), "c.txt: Note line is missing\nc.txt:3:1: Wrong line\n    x\n    ^");
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(edit_distance("Tie", "TI"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(suggest("Of", &["On", "Off"]), Some("Off"));
+        assert_eq!(suggest("of", &["Off", "On"]), Some("Off"));
+        assert_eq!(suggest("Onn", &["Off", "On"]), Some("On"));
+        assert_eq!(suggest("X", &["UP", "DN"]), None);
+        assert_eq!(suggest("Slides", &["Note", "Accent"]), None);
+        assert_eq!(did_you_mean("Acent", &["Note", "Accent"]), ", did you mean Accent?");
+        assert_eq!(did_you_mean("Q", &["Note", "Accent"]), "");
+    }
+}
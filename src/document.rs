@@ -0,0 +1,433 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::bank::{file_to_bank, parse_slot_header, slot_header};
+use crate::metadata::{find_metadata_field, metadata_to_string, Metadata};
+use crate::pattern::{
+    file_to_pattern_with_metadata, find_field, pattern_to_string_with_metadata, pattern_to_string_with_unknown,
+    string_to_pattern_with_metadata, Field, Pattern, FIELDS,
+};
+
+// pattern file as it was written by user, so it could be changed and written back with comments and blank lines intact
+pub struct Document {
+    lines: Vec<Line>,
+}
+
+// line split at //, code is kept as is, comment includes whitespace before // so alignment survives
+struct Line {
+    code: String,
+    comment: String,
+}
+
+const HEADER: &str = "TD-3 Pattern";
+const HEADER_V2: &str = "TD-3 Pattern v2";
+
+impl Line {
+    fn parse(line: &str) -> Line {
+        match line.find("//") {
+            Some(x) => {
+                let code = line[0..x].trim_end();
+                Line { code: code.to_owned(), comment: line[code.len()..].to_owned() }
+            }
+            None => Line { code: line.to_owned(), comment: String::from("") },
+        }
+    }
+
+    // part before : for data lines, e.g. Note or Active Steps
+    fn key(&self) -> Option<&str> {
+        self.code.split_once(':').map(|(key, _)| key.trim())
+    }
+
+    // pattern data line, replaced by set_pattern
+    fn field(&self) -> Option<Field> {
+        self.key().and_then(find_field)
+    }
+
+    fn is_header(&self) -> bool {
+        let code = self.code.trim();
+        code.eq_ignore_ascii_case(HEADER) || code.eq_ignore_ascii_case(HEADER_V2)
+    }
+
+    // comment only line like // 05: text
+    fn annotation(&self) -> Option<(usize, &str)> {
+        if !self.code.trim().is_empty() {
+            return None;
+        }
+        let (step, text) = self.comment.trim().strip_prefix("//")?.split_once(':')?;
+        match step.trim().parse::<usize>() {
+            Ok(step @ 1..=16) => Some((step, text.trim())),
+            _ => None,
+        }
+    }
+}
+
+// what line of canonical file the line corresponds to, Active Steps and Triplet share one line
+#[derive(PartialEq)]
+enum Kind<'a> {
+    Header,
+    Field(Field),
+    Metadata(&'a str),
+}
+
+impl Line {
+    fn kind(&self) -> Option<Kind<'_>> {
+        match self.field() {
+            _ if self.is_header() => Some(Kind::Header),
+            Some(Field::Triplet) => Some(Kind::Field(Field::ActiveSteps)),
+            Some(field) => Some(Kind::Field(field)),
+            None => self.key().and_then(find_metadata_field).map(Kind::Metadata),
+        }
+    }
+}
+
+fn annotation_line(step: usize, text: &str) -> Line {
+    Line { code: String::from(""), comment: format!("// {:02}: {}", step, text) }
+}
+
+impl Document {
+    // file should be valid pattern, so everything read from document later is valid too
+    pub fn parse(text: &str) -> Result<Document, Box<dyn Error>> {
+        string_to_pattern_with_metadata(text.to_owned())?;
+        Ok(Document { lines: text.lines().map(Line::parse).collect() })
+    }
+
+    pub fn from_pattern(pattern: &Pattern, metadata: &Metadata) -> Document {
+        Document { lines: pattern_to_string_with_metadata(pattern, metadata).lines().map(Line::parse).collect() }
+    }
+
+    // pattern as it's written, Unknown line stays if document has it
+    fn canonical(&self, pattern: &Pattern, metadata: &Metadata) -> Document {
+        let unknown = self.lines.iter().any(|l| l.field() == Some(Field::Unknown));
+        let text = pattern_to_string_with_unknown(pattern, metadata, unknown);
+        Document { lines: text.lines().map(Line::parse).collect() }
+    }
+
+    pub fn pattern(&self) -> Result<(Pattern, Metadata), Box<dyn Error>> {
+        string_to_pattern_with_metadata(self.to_string())
+    }
+
+    // step lines are replaced with canonical ones, Active Steps and Triplet keep their spelling,
+    // comments after them and everything else is kept
+    pub fn set_pattern(&mut self, pattern: &Pattern) {
+        let canonical = self.canonical(pattern, &Default::default());
+        let steps_line = canonical.lines.iter().find(|l| l.field() == Some(Field::ActiveSteps)).unwrap();
+        let steps_value = |field: Field| {
+            steps_line
+                .code
+                .split(',')
+                .filter_map(|part| part.split_once(':'))
+                .find(|(key, _)| find_field(key) == Some(field))
+        };
+        for line in self.lines.iter_mut() {
+            match line.field() {
+                Some(Field::ActiveSteps | Field::Triplet) => {
+                    let parts = line.code.split(',').filter_map(|part| part.split_once(':')).map(|(key, value)| {
+                        match find_field(key).and_then(steps_value) {
+                            Some((_, new)) => format!("{}:{}", key, new),
+                            None => format!("{}:{}", key, value),
+                        }
+                    });
+                    line.code = parts.collect::<Vec<String>>().join(",");
+                }
+                Some(field) => {
+                    if let Some(new) = canonical.lines.iter().find(|l| l.field() == Some(field)) {
+                        line.code = new.code.clone();
+                    }
+                }
+                None => (),
+            }
+        }
+        // lenient files could miss lines, e.g. Transpose when notes have octaves, canonical ones are added after the rest
+        let covered = self
+            .lines
+            .iter()
+            .flat_map(|l| {
+                l.code.split(',').filter_map(|part| part.split_once(':')).filter_map(|(key, _)| find_field(key))
+            })
+            .collect::<Vec<Field>>();
+        let mut at = self.lines.iter().rposition(|l| l.field().is_some()).map_or(self.lines.len(), |n| n + 1);
+        for field in FIELDS.iter().map(|(field, _)| *field).filter(|field| !covered.contains(field)) {
+            let code = match field {
+                Field::ActiveSteps | Field::Triplet => {
+                    steps_value(field).map(|(key, value)| format!("{}:{}", key.trim(), value))
+                }
+                _ => canonical.lines.iter().find(|l| l.field() == Some(field)).map(|l| l.code.clone()),
+            };
+            if let Some(code) = code {
+                self.lines.insert(at, Line { code, comment: String::from("") });
+                at += 1;
+            }
+        }
+    }
+
+    // metadata lines are replaced, fields which stay keep their comments, header switches between v1 and v2
+    pub fn set_metadata(&mut self, metadata: &Metadata) {
+        let header = self.lines.iter().position(|l| l.is_header()).unwrap_or(0);
+        let end = self.lines.len();
+        let is_metadata = |l: &Line| l.key().and_then(find_metadata_field).is_some();
+        let old_positions = (header..end).filter(|&n| is_metadata(&self.lines[n])).collect::<Vec<usize>>();
+        let mut new_lines = metadata_to_string(metadata).lines().map(Line::parse).collect::<Vec<Line>>();
+        let mut old_lines = Vec::new();
+        for n in old_positions.iter().rev() {
+            old_lines.insert(0, self.lines.remove(*n));
+        }
+        for new in new_lines.iter_mut() {
+            if let Some(x) = old_lines.iter().position(|old| old.key().and_then(find_metadata_field) == new.key()) {
+                new.comment = old_lines.remove(x).comment;
+            }
+        }
+        let at = match old_positions.first() {
+            Some(&n) => n,
+            None => {
+                // blank line between new metadata and the rest, like in canonical file
+                if !new_lines.is_empty() {
+                    new_lines.push(Line::parse(""));
+                }
+                header + 1
+            }
+        };
+        self.lines.splice(at..at, new_lines);
+        if let Some(line) = self.lines.get_mut(header) {
+            line.code = String::from(if metadata.is_empty() { HEADER } else { HEADER_V2 });
+        }
+    }
+
+    // step 1-16, text of // NN: text comment lines
+    pub fn annotations(&self) -> Vec<(usize, String)> {
+        self.lines.iter().filter_map(|l| l.annotation()).map(|(step, text)| (step, text.to_owned())).collect()
+    }
+
+    // annotations are kept after pattern lines in step order, None removes annotation
+    pub fn set_annotation(&mut self, step: usize, text: Option<&str>) {
+        let existing = self.lines.iter().position(|l| l.annotation().map(|(s, _)| s) == Some(step));
+        match (existing, text) {
+            (Some(n), Some(text)) => self.lines[n] = annotation_line(step, text),
+            (Some(n), None) => {
+                self.lines.remove(n);
+            }
+            (None, Some(text)) => {
+                let after = self
+                    .lines
+                    .iter()
+                    .rposition(|l| l.annotation().is_some_and(|(s, _)| s < step) || l.field() == Some(Field::Time))
+                    .map_or(self.lines.len(), |n| n + 1);
+                self.lines.insert(after, annotation_line(step, text));
+            }
+            (None, None) => (),
+        }
+    }
+}
+
+// first line which isn't blank or comment is TD-3 Pattern header, even misspelled header is taken as not a pattern
+pub fn is_pattern_file(text: &str) -> bool {
+    text.lines().map(Line::parse).find(|l| !l.code.trim().is_empty()).is_some_and(|l| l.is_header())
+}
+
+// file as pattern_to_string would write it, comments are kept: comment after a line stays after the same line,
+// comment lines go before the line which followed them
+pub fn format_pattern(filename: &str, text: &str) -> Result<String, Box<dyn Error>> {
+    let (pattern, metadata) = file_to_pattern_with_metadata(filename, text.to_owned())?;
+    let canonical = Document { lines: text.lines().map(Line::parse).collect() }.canonical(&pattern, &metadata);
+    // step numbers and other comment lines of canonical file are always written
+    let generated = canonical.lines.iter().filter(|l| l.code.is_empty()).map(|l| l.comment.trim().to_owned());
+    let generated = generated.collect::<Vec<String>>();
+    // canonical line and whether user's line was matched to it already
+    let mut lines = canonical.lines.into_iter().map(|l| (l, false)).collect::<Vec<(Line, bool)>>();
+    let mut comments = Vec::new();
+    for line in text.lines().map(Line::parse) {
+        let comment = line.comment.trim();
+        let kind = match line.kind() {
+            Some(kind) => kind,
+            None => {
+                // blank lines are where canonical file has them
+                if !comment.is_empty() && !generated.iter().any(|g| g == comment) {
+                    comments.push(Line { code: String::from(""), comment: comment.to_owned() });
+                }
+                continue;
+            }
+        };
+        // Notes could be repeated, every one goes to its own line, steps line takes both Active Steps and Triplet
+        let shared = kind == Kind::Field(Field::ActiveSteps);
+        let at = match lines.iter().position(|(l, used)| (!used || shared) && l.kind().as_ref() == Some(&kind)) {
+            Some(at) => at,
+            None => continue,
+        };
+        // legend comments are replaced, padding before them is kept so comments stay aligned
+        let (line, used) = &mut lines[at];
+        if !comment.is_empty() {
+            line.comment = match (*used, line.comment.find("//")) {
+                (true, _) if !line.comment.is_empty() => format!("{} {}", line.comment, comment),
+                (_, Some(x)) => format!("{}{}", &line.comment[0..x], comment),
+                (_, None) => format!("  {}", comment),
+            };
+        }
+        *used = true;
+        lines.splice(at..at, comments.drain(..).map(|l| (l, true)));
+    }
+    lines.extend(comments.into_iter().map(|l| (l, true)));
+    Ok(Document { lines: lines.into_iter().map(|(l, _)| l).collect() }.to_string())
+}
+
+// every section of bank file is formatted as pattern file under canonical [Group 2 Pattern 3B] header,
+// lines before the first header are kept as they are
+pub fn format_bank(filename: &str, text: &str) -> Result<String, Box<dyn Error>> {
+    // errors are reported with line numbers of bank file, so sections below are always valid
+    let entries = file_to_bank(filename, text.to_owned())?;
+    let mut preamble = String::new();
+    let mut sections: Vec<(Line, Vec<&str>)> = Vec::new();
+    for line in text.lines() {
+        let parsed = Line::parse(line);
+        match (parse_slot_header(&parsed.code), sections.last_mut()) {
+            (Some(_), _) => sections.push((parsed, Vec::new())),
+            (None, Some((_, lines))) => lines.push(line),
+            (None, None) => preamble.push_str(&format!("{}\n", line)),
+        }
+    }
+    let mut formatted = Vec::new();
+    for ((header, lines), entry) in sections.into_iter().zip(entries.iter()) {
+        let pattern = format_pattern(filename, &lines.join("\n"))?;
+        formatted.push(format!("{}{}\n{}", slot_header(entry.slot), header.comment, pattern));
+    }
+    Ok(preamble + &formatted.join("\n"))
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.lines.iter() {
+            writeln!(f, "{}{}", line.code, line.comment)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::bank_to_string;
+    use crate::step::{Accent, Slide};
+
+    const FILE: &str = "// bassline for the second part
+TD-3 Pattern v2
+name: Confusion  // working title
+Tags: acid
+
+Active Steps: 16, Triplet Time: Off
+
+// Step:    01, 02, 03, 04, 05, 06, 07, 08, 09, 10, 11, 12, 13, 14, 15, 16
+Note:       D#, D#, C#, C#, C#, G#, D#, D#, G#, D#, E , D#, D#, C#, D#, G#  // C -C# .. B -C^
+Transpose:    ,   , DN,   , DN, UP, UP, UP, UP, UP,   , UP,   , UP, DN, UP
+// accents are for the second bar
+Accent:       , AC,   ,   ,   ,   ,   ,   ,   ,   ,   ,   ,   ,   ,   ,     // keep them
+Slide:        , SL,   ,   ,   ,   ,   , SL,   ,   ,   ,   ,   ,   ,   ,
+Tie/Rest:     , TI,   ,   ,   , TI, TI,   ,   , TI, TI,   ,   , TR, TI,
+// 03: open filter here
+// 12: closed again
+";
+
+    #[test]
+    fn comments_survive_pattern_change() {
+        let mut document = Document::parse(FILE).unwrap();
+        let (mut pattern, metadata) = document.pattern().unwrap();
+        pattern[0].accent = Accent::On;
+        pattern[1].slide = Slide::Off;
+        document.set_pattern(&pattern);
+        let text = document.to_string();
+        for line in FILE.lines().filter(|l| l.trim_start().starts_with("//") && !l.contains("Step:")) {
+            assert!(text.contains(line), "{} is lost:\n{}", line, text);
+        }
+        for comment in ["// working title", "// keep them", "// C -C# .. B -C^"] {
+            assert!(text.contains(comment), "{} is lost:\n{}", comment, text);
+        }
+        assert!(text.lines().any(|l| l.starts_with("Accent:") && l.contains("AC, AC,")), "{}", text);
+        assert_eq!(Document::parse(&text).unwrap().pattern().unwrap(), (pattern, metadata));
+    }
+
+    #[test]
+    fn metadata_change_keeps_comments() {
+        let mut document = Document::parse(FILE).unwrap();
+        let (_, mut metadata) = document.pattern().unwrap();
+        metadata.author = Some(String::from("beholder"));
+        document.set_metadata(&metadata);
+        let text = document.to_string();
+        assert!(text.contains("Name: Confusion  // working title"), "{}", text);
+        assert_eq!(Document::parse(&text).unwrap().pattern().unwrap().1, metadata);
+        // no metadata switches file back to v1
+        document.set_metadata(&Default::default());
+        let text = document.to_string();
+        assert!(text.lines().any(|l| l == HEADER), "{}", text);
+        assert!(!text.contains("Name:") && !text.contains("Tags:"), "{}", text);
+    }
+
+    #[test]
+    fn annotations_round_trip() {
+        let mut document = Document::parse(FILE).unwrap();
+        assert_eq!(
+            document.annotations(),
+            vec![(3, String::from("open filter here")), (12, String::from("closed again"))]
+        );
+        document.set_annotation(1, Some("start"));
+        document.set_annotation(16, Some("end"));
+        document.set_annotation(3, Some("open filter"));
+        document.set_annotation(12, None);
+        document.set_annotation(5, None);
+        let expected = vec![(1, String::from("start")), (3, String::from("open filter")), (16, String::from("end"))];
+        assert_eq!(document.annotations(), expected);
+        let reread = Document::parse(&document.to_string()).unwrap();
+        assert_eq!(reread.annotations(), expected);
+        assert_eq!(reread.pattern().unwrap(), document.pattern().unwrap());
+    }
+
+    #[test]
+    fn fmt_is_idempotent() {
+        let lenient = "TD-3 Pattern  // from the jam
+triplet time: on, active steps: 8
+note: c, D, e, F, g, a, b, C^  // scale
+// no accents yet
+transpose: , , , , , , , up
+tie/rest: , , , , , , , TI
+// 02: higher
+";
+        for text in [FILE, lenient] {
+            let formatted = format_pattern("test", text).unwrap();
+            assert_eq!(format_pattern("test", &formatted).unwrap(), formatted);
+            assert_eq!(
+                file_to_pattern_with_metadata("test", formatted.clone()).unwrap(),
+                file_to_pattern_with_metadata("test", text.to_owned()).unwrap()
+            );
+            for comment in text.lines().filter_map(|l| l.find("//").map(|x| l[x..].trim())) {
+                if !comment.starts_with("// Step:") {
+                    assert!(formatted.contains(comment), "{} is lost:\n{}", comment, formatted);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bank_is_formatted_by_section() {
+        let bank = "// live set
+[group 1 pattern 2a]  // intro
+TD-3 Pattern
+triplet time: on, active steps: 8
+note: c, D, e, F, g, a, b, C^  // scale
+
+[Group 2 Pattern 1B]
+TD-3 Pattern
+Note: C
+";
+        let formatted = format_bank("test", bank).unwrap();
+        assert!(
+            formatted.starts_with("// live set\n[Group 1 Pattern 2A]  // intro\nTD-3 Pattern\n"),
+            "{}",
+            formatted
+        );
+        assert!(formatted.contains("// scale") && formatted.contains("\n\n[Group 2 Pattern 1B]\nTD-3 Pattern\n"));
+        assert_eq!(format_bank("test", &formatted).unwrap(), formatted);
+        // bank written by download is formatted already
+        let written = bank_to_string(&file_to_bank("test", formatted.clone()).unwrap(), false);
+        assert_eq!(format_bank("test", &written).unwrap(), written);
+        assert!(format_bank("test", "[Group 1 Pattern 1A]\nTD-3 Pattern\nNote: X\n")
+            .unwrap_err()
+            .to_string()
+            .starts_with("test:3:"));
+    }
+}
@@ -0,0 +1,269 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+// firmware version like 1.3.7, compared part by part as numbers, so 1.10 is above 1.9 and 1.2 is 1.2.0
+#[derive(Debug, Clone)]
+pub struct Version {
+    parts: Vec<u8>,
+}
+
+// parts without trailing zeros, what is compared and hashed
+fn significant(parts: &[u8]) -> &[u8] {
+    let len = parts.iter().rposition(|x| *x != 0).map_or(0, |n| n + 1);
+    &parts[0..len]
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Version) -> bool {
+        significant(&self.parts) == significant(&other.parts)
+    }
+}
+
+impl Eq for Version {}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        significant(&self.parts).cmp(significant(&other.parts))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        significant(&self.parts).hash(state);
+    }
+}
+
+impl Version {
+    pub fn new(parts: &[u8]) -> Version {
+        Version { parts: parts.to_vec() }
+    }
+
+    // firmware version response is message id, 00, then one byte per part
+    pub fn from_sysex(msg: &[u8]) -> Result<Version, Box<dyn Error>> {
+        if msg.len() < 3 {
+            return Err(format!("Response for firmware version is too short: {:02x?}", msg).into());
+        }
+        Ok(Version::new(&msg[2..]))
+    }
+
+    pub fn parts(&self) -> &[u8] {
+        &self.parts
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.parts.iter().map(|x| x.to_string()).collect::<Vec<String>>().join("."))
+    }
+}
+
+impl FromStr for Version {
+    type Err = Box<dyn Error>;
+    fn from_str(input: &str) -> Result<Version, Self::Err> {
+        match input.trim().trim_end_matches('.').split('.').map(|x| x.parse::<u8>()).collect() {
+            Ok(parts) => Ok(Version { parts }),
+            Err(_) => Err(format!("Wrong firmware version: {}", input).into()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Feature {
+    PatternDownload,
+    PatternUpload,
+    Track,
+    Settings,
+}
+
+pub const FEATURES: &[Feature] = &[Feature::PatternDownload, Feature::PatternUpload, Feature::Track, Feature::Settings];
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Feature::PatternDownload => write!(f, "pattern download"),
+            Feature::PatternUpload => write!(f, "pattern upload"),
+            Feature::Track => write!(f, "track download/upload"),
+            Feature::Settings => write!(f, "settings"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Support {
+    Yes,
+    // should work, but nobody has confirmed it on this firmware yet
+    Unconfirmed,
+    No,
+}
+
+impl fmt::Display for Support {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Support::Yes => write!(f, "yes"),
+            Support::Unconfirmed => write!(f, "unconfirmed"),
+            Support::No => write!(f, "no"),
+        }
+    }
+}
+
+struct Compatibility {
+    // lowest version entry applies to, entry with highest min which is not above version is used
+    min: &'static [u8],
+    features: &'static [(Feature, Support)],
+}
+
+const COMPATIBILITY: &[Compatibility] = &[Compatibility {
+    min: &[],
+    features: &[
+        (Feature::PatternDownload, Support::Yes),
+        (Feature::PatternUpload, Support::Yes),
+        (Feature::Track, Support::Unconfirmed),
+        (Feature::Settings, Support::Unconfirmed),
+    ],
+}];
+
+pub struct Problem {
+    pub version: &'static [u8],
+    // refuse to work at all or just warn
+    pub refuse: bool,
+    pub message: &'static str,
+}
+
+// firmware versions which are known to misbehave, please report them with an issue
+pub const PROBLEMS: &[Problem] = &[];
+
+pub fn support(version: &Version, feature: Feature) -> Support {
+    table_support(COMPATIBILITY, version, feature)
+}
+
+fn table_support(table: &[Compatibility], version: &Version, feature: Feature) -> Support {
+    let compatibility = table
+        .iter()
+        .filter(|c| Version::new(c.min) <= *version)
+        .max_by(|a, b| Version::new(a.min).cmp(&Version::new(b.min)));
+    match compatibility.and_then(|c| c.features.iter().find(|(f, _)| *f == feature)) {
+        Some((_, support)) => *support,
+        None => Support::No,
+    }
+}
+
+// error for feature which isn't supported, warning for unconfirmed
+pub fn require(version: &Version, feature: Feature) -> Result<Option<String>, Box<dyn Error>> {
+    match support(version, feature) {
+        Support::Yes => Ok(None),
+        Support::Unconfirmed => Ok(Some(format!("Warning: {} is not confirmed on firmware {}", feature, version))),
+        Support::No => Err(format!("Firmware {} doesn't support {}", version, feature).into()),
+    }
+}
+
+// made up sysex of unconfirmed feature could write garbage to device, so it's refused unless user allows it
+pub fn require_write(
+    version: &Version,
+    feature: Feature,
+    allow_unconfirmed: bool,
+) -> Result<Option<String>, Box<dyn Error>> {
+    match support(version, feature) {
+        Support::Unconfirmed if !allow_unconfirmed => Err(format!(
+            "Writing {} is not confirmed on firmware {}, --allow-unconfirmed sends it anyway",
+            feature, version
+        )
+        .into()),
+        _ => require(version, feature),
+    }
+}
+
+// error for versions which shouldn't be used at all, warning for the rest of known problems
+pub fn check_version(version: &Version) -> Result<Option<String>, Box<dyn Error>> {
+    check_problems(PROBLEMS, version)
+}
+
+fn check_problems(problems: &[Problem], version: &Version) -> Result<Option<String>, Box<dyn Error>> {
+    match problems.iter().find(|p| Version::new(p.version) == *version) {
+        Some(p) if p.refuse => Err(format!("Firmware {} is not supported: {}", version, p.message).into()),
+        Some(p) => Ok(Some(format!("Warning: firmware {}: {}", version, p.message))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(input: &str) -> Version {
+        Version::from_str(input).unwrap()
+    }
+
+    #[test]
+    fn versions_are_compared_as_numbers() {
+        assert!(version("1.10") > version("1.9"));
+        assert!(version("1.3.7") > version("1.3") && version("2") > version("1.99.99"));
+        assert_eq!(version("1.2"), version("1.2.0"));
+        assert_eq!(version("1.2").cmp(&version("1.2.0")), Ordering::Equal);
+        assert_eq!(version(" 1.3.7. ").to_string(), "1.3.7");
+        assert!(Version::from_str("1.x").is_err() && Version::from_str("1.256").is_err());
+    }
+
+    #[test]
+    fn version_is_read_from_reply() {
+        // message id, 00, then one byte per part, it used to be printed as 1.3.7. with trailing dot
+        let read = Version::from_sysex(&[0x09, 0x00, 0x01, 0x03, 0x07]).unwrap();
+        assert_eq!(read, version("1.3.7."));
+        assert_eq!(read.to_string(), "1.3.7");
+        assert!(Version::from_sysex(&[0x09, 0x00]).is_err());
+    }
+
+    #[test]
+    fn support_is_taken_from_the_latest_matching_entry() {
+        let table = [
+            Compatibility { min: &[], features: &[(Feature::PatternDownload, Support::Yes)] },
+            Compatibility {
+                min: &[1, 10],
+                features: &[(Feature::PatternDownload, Support::Yes), (Feature::Track, Support::Unconfirmed)],
+            },
+        ];
+        assert_eq!(table_support(&table, &version("1.9"), Feature::Track), Support::No);
+        assert_eq!(table_support(&table, &version("1.10.0"), Feature::Track), Support::Unconfirmed);
+        assert_eq!(table_support(&table, &version("1.9"), Feature::PatternDownload), Support::Yes);
+        assert_eq!(support(&version("1.3.7"), Feature::PatternUpload), Support::Yes);
+        assert_eq!(support(&version("1.3.7"), Feature::Track), Support::Unconfirmed);
+    }
+
+    #[test]
+    fn require_refuses_what_is_not_supported() {
+        let version = version("1.3.7");
+        assert_eq!(require(&version, Feature::PatternDownload).unwrap(), None);
+        assert!(require(&version, Feature::Settings).unwrap().unwrap().contains("not confirmed"));
+        let table = [Compatibility { min: &[], features: &[] }];
+        assert_eq!(table_support(&table, &version, Feature::Track), Support::No);
+    }
+
+    #[test]
+    fn problematic_versions_are_reported() {
+        let problems = [
+            Problem { version: &[1, 2], refuse: true, message: "patterns are corrupted" },
+            Problem { version: &[1, 3, 7], refuse: false, message: "triplets are off" },
+        ];
+        assert!(check_problems(&problems, &version("1.2.0")).is_err());
+        assert!(check_problems(&problems, &version("1.3.7")).unwrap().unwrap().contains("triplets are off"));
+        assert_eq!(check_problems(&problems, &version("1.3.8")).unwrap(), None);
+        assert_eq!(check_version(&version("1.3.7")).unwrap(), None);
+    }
+
+    #[test]
+    fn unconfirmed_writes_need_permission() {
+        let version = Version::new(&[1, 3, 7]);
+        assert!(require_write(&version, Feature::Track, false).is_err());
+        assert!(require_write(&version, Feature::Track, true).unwrap().is_some());
+        assert!(require_write(&version, Feature::Settings, false).is_err());
+        assert_eq!(require_write(&version, Feature::PatternUpload, false).unwrap(), None);
+    }
+}
@@ -0,0 +1,33 @@
+extern crate argmap;
+#[cfg(feature = "midi")]
+extern crate midir;
+
+// status message to stderr, stdout is left for pattern data, --quiet turns them off
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::config::quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub mod bank;
+pub mod config;
+pub mod device;
+pub mod diagnostic;
+pub mod document;
+pub mod firmware;
+pub mod lint;
+pub mod metadata;
+#[cfg(feature = "midi")]
+pub mod midicomm;
+pub mod pattern;
+pub mod settings;
+pub mod slot;
+pub mod step;
+#[cfg(feature = "midi")]
+pub mod sync;
+pub mod track;
+#[cfg(feature = "midi")]
+pub mod watch;
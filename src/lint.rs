@@ -0,0 +1,202 @@
+use std::fmt;
+
+use crate::pattern::{Pattern, NOTE};
+use crate::step::{Accent, Slide, Step, Time, Transpose};
+
+// kinds of problems, code is printed so warnings could be filtered by scripts
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Lint {
+    // note which is never reached by note pointer, see Sequencer Quirks in README.md
+    UnplayedNote,
+    TieOnFirstStep,
+    TieAcrossLoop,
+    SlideIntoRest,
+    ContentBeyondActiveSteps,
+    AccentOnRest,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Lint::UnplayedNote => write!(f, "unplayed-note"),
+            Lint::TieOnFirstStep => write!(f, "tie-on-first-step"),
+            Lint::TieAcrossLoop => write!(f, "tie-across-loop"),
+            Lint::SlideIntoRest => write!(f, "slide-into-rest"),
+            Lint::ContentBeyondActiveSteps => write!(f, "content-beyond-active-steps"),
+            Lint::AccentOnRest => write!(f, "accent-on-rest"),
+        }
+    }
+}
+
+// step is 1-based column in pattern file, None for pattern as a whole
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub step: Option<usize>,
+    pub message: String,
+}
+
+// step:lint: message, step is empty for pattern as a whole
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.step {
+            Some(step) => write!(f, "{}:{}: {}", step, self.lint, self.message),
+            None => write!(f, ":{}: {}", self.lint, self.message),
+        }
+    }
+}
+
+fn is_rest(time: Time) -> bool {
+    matches!(time, Time::Rest | Time::TieRest)
+}
+
+// step which differs from what missing step is read as
+fn has_content(step: &Step) -> bool {
+    step.note() != 0
+        || step.transpose() != Transpose::Normal
+        || step.accent != Accent::Off
+        || step.slide != Slide::Off
+        || step.time != Time::Normal
+}
+
+// time row advances every step, note row advances only when a new note starts: on a step which isn't rest
+// and doesn't continue a tie, returns (step, note) pairs, both 0-based
+pub fn played_notes(pattern: &Pattern) -> Vec<(usize, usize)> {
+    let active_steps = (pattern.active_steps as usize).min(16);
+    let mut played = Vec::new();
+    let mut tied = false;
+    for i in 0..active_steps {
+        let time = pattern.step[i].time;
+        if !is_rest(time) && !tied {
+            played.push((i, played.len()));
+        }
+        tied = time == Time::Tie;
+    }
+    played
+}
+
+pub fn lint(pattern: &Pattern) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut warn = |lint: Lint, step: Option<usize>, message: String| warnings.push(Warning { lint, step, message });
+    // pattern checks active steps, min is for steps slice only
+    let active_steps = (pattern.active_steps as usize).min(16);
+    let steps = &pattern.step[0..active_steps];
+    let played = played_notes(pattern);
+    for (i, step) in steps.iter().enumerate().skip(played.len()) {
+        // tie or rest of the step is still used, only the note part isn't
+        let mut note_part = *step;
+        note_part.time = Time::Normal;
+        if has_content(&note_part) {
+            let message = format!(
+                "Note {} is never played, only {} note(s) are played because of ties and rests",
+                NOTE[step.note() as usize],
+                played.len()
+            );
+            warn(Lint::UnplayedNote, Some(i + 1), message);
+        }
+    }
+    if steps.first().is_some_and(|s| s.time == Time::Tie) {
+        let message = String::from(
+            "Tie on the first step holds the first note, notes written from step 2 are played a step later",
+        );
+        warn(Lint::TieOnFirstStep, Some(1), message);
+    }
+    if active_steps > 0 && steps[active_steps - 1].time == Time::Tie {
+        let message = String::from("Tie on the last active step carries over the loop point into step 1");
+        warn(Lint::TieAcrossLoop, Some(active_steps), message);
+    }
+    for &(at, note) in played.iter() {
+        // slide goes to next step, after the last one it's step 1 of the next loop
+        let next = (at + 1) % active_steps;
+        if steps[note].slide == Slide::On && is_rest(steps[next].time) {
+            let message = format!(
+                "Slide of note {} played on step {} goes into rest on step {}",
+                NOTE[steps[note].note() as usize],
+                at + 1,
+                next + 1
+            );
+            warn(Lint::SlideIntoRest, Some(note + 1), message);
+        }
+    }
+    // accent goes with note like slide does, so accent written in rest column is played on another step
+    for &(at, note) in played.iter() {
+        if steps[note].accent == Accent::On && is_rest(steps[note].time) {
+            let message = format!(
+                "Accent is written on rest step {}, it's played with note {} on step {}",
+                note + 1,
+                NOTE[steps[note].note() as usize],
+                at + 1
+            );
+            warn(Lint::AccentOnRest, Some(note + 1), message);
+        }
+    }
+    for (i, step) in pattern.step.iter().enumerate().skip(active_steps) {
+        if has_content(step) {
+            let message = format!("Step has content but pattern has only {} active steps", active_steps);
+            warn(Lint::ContentBeyondActiveSteps, Some(i + 1), message);
+        }
+    }
+    // in step order, warnings about pattern as a whole first
+    warnings.sort_by_key(|w| w.step);
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::string_to_pattern;
+
+    // 4 step pattern with one more row and Tie/Rest row
+    fn lints(row: &str, time: &str) -> Vec<(Lint, Option<usize>)> {
+        let text = format!("TD-3 Pattern\nActive Steps: 4\nNote: C, D, E, F\n{}\nTie/Rest: {}\n", row, time);
+        lint(&string_to_pattern(text).unwrap()).into_iter().map(|w| (w.lint, w.step)).collect()
+    }
+
+    #[test]
+    fn pattern_which_plays_as_written_has_no_warnings() {
+        assert_eq!(lints("Accent: AC, , AC,", ", , ,"), vec![]);
+        assert_eq!(lints("Slide: SL, , SL,", ", , ,"), vec![]);
+    }
+
+    #[test]
+    fn every_rule_fires() {
+        // D is held over step 3, so F isn't reached
+        assert_eq!(lints("Slide: , , ,", ", TI, ,"), vec![(Lint::UnplayedNote, Some(4))]);
+        assert_eq!(
+            lints("Slide: , , ,", "TI, , ,"),
+            vec![(Lint::TieOnFirstStep, Some(1)), (Lint::UnplayedNote, Some(4))]
+        );
+        assert_eq!(lints("Slide: , , ,", ", , , TI"), vec![(Lint::TieAcrossLoop, Some(4))]);
+        let text = "TD-3 Pattern\nActive Steps: 2\nNote: C, D, E\n";
+        let found = lint(&string_to_pattern(text.to_owned()).unwrap()).into_iter().map(|w| (w.lint, w.step));
+        assert_eq!(found.collect::<Vec<_>>(), vec![(Lint::ContentBeyondActiveSteps, Some(3))]);
+    }
+
+    // slide and accent are taken by note index: column of the note which is played, not column of the step
+    #[test]
+    fn slide_and_accent_go_with_notes() {
+        // C slides into rest on step 2
+        assert_eq!(
+            lints("Slide: SL, , ,", ", RE, ,"),
+            vec![(Lint::SlideIntoRest, Some(1)), (Lint::UnplayedNote, Some(4))]
+        );
+        // D is played on step 3, its slide goes into rest on step 4
+        assert_eq!(
+            lints("Slide: , SL, ,", ", RE, , RE"),
+            vec![(Lint::SlideIntoRest, Some(2)), (Lint::UnplayedNote, Some(3)), (Lint::UnplayedNote, Some(4))]
+        );
+        // slide written in rest column belongs to D which is followed by a note, it's fine
+        assert_eq!(lints("Slide: , SL, ,", ", RE, ,"), vec![(Lint::UnplayedNote, Some(4))]);
+        // accent written in rest column 2 is played with D on step 3
+        let warnings = lint(
+            &string_to_pattern(String::from(
+                "TD-3 Pattern\nActive Steps: 4\nNote: C, D, E\nAccent: , AC\nTie/Rest: , RE\n",
+            ))
+            .unwrap(),
+        );
+        assert_eq!(warnings.iter().map(|w| (w.lint, w.step)).collect::<Vec<_>>(), vec![(Lint::AccentOnRest, Some(2))]);
+        assert!(warnings[0].message.contains("note D on step 3"), "{}", warnings[0].message);
+        // accent of E is written in column 3 which isn't a rest, E is played a step later on step 4
+        assert_eq!(lints("Accent: , , AC,", ", RE, ,"), vec![(Lint::UnplayedNote, Some(4))]);
+    }
+}
@@ -1,109 +1,421 @@
-extern crate argmap;
-extern crate midir;
 use std::error::Error;
-use std::fs::{read_to_string, File};
+use std::fs::{read, read_dir, read_to_string, File};
+use std::io;
 use std::io::prelude::*;
-use std::sync::mpsc;
+use std::path::{Path, PathBuf};
 
-mod config;
-mod step;
-use config::{Config, Mode, DEFAULT_PORTNAME};
-mod midicomm;
-use midicomm::{get_pattern, open_ports, send_sysex};
-mod pattern;
-use pattern::{pattern_to_string, pattern_to_sysex, string_to_pattern, sysex_to_pattern};
+use td3_pattern::bank::{file_to_bank, is_bank, slot_header};
+use td3_pattern::config;
+use td3_pattern::config::{Config, Mode, STDIO};
+use td3_pattern::document::{format_bank, format_pattern, is_pattern_file};
+use td3_pattern::lint::lint;
+use td3_pattern::pattern::{file_to_pattern_with_metadata, Pattern};
+use td3_pattern::status;
 
-const USAGE: &'static str = "
+#[cfg(feature = "midi")]
+mod device_commands;
+
+const USAGE: &str = "
 Usage:
-    td3pattern [-in=\"name\"] [-out=\"name\"] <group> <pattern><a|b> [-file=filename]
-    td3pattern [-in=\"name\"] [-out=\"name\"] upload <group> <pattern><a|b> -file=filename
-Where:
-    -in=\"name\" -- name of TD-3's midi in port
-    -out=\"name\" -- name of TD-3's midi out port
-    -file=filename -- file for saving or loading pattern, in case of saving if not specified stdin is used
-    <group> -- Group 1-4
-    <pattern><a|b> - Pattern 1-8 A-B
+    td3pattern [options] <command> [arguments]
+Commands:
+    list -- show midi ports and TD-3 devices found on them
+    info -- show model, firmware version and which features are supported by it
+    download <slot>... [-file=filename] -- print pattern(s) or save pattern to file
+    upload <slot> -file=filename -- upload pattern file to device, slots could be left out for bank file
+    backup [<slot>...] -dir=directory [--dry-run] [--yes] -- save patterns to directory
+    restore [<slot>...] -dir=directory [--dry-run] [--yes] -- upload patterns from directory
+    sync <pull|push> -dir=directory [--dry-run] [--yes] -- backup or restore whole device
+    watch <slot> -file=filename -- upload pattern file every time it's saved
+    download track <track> [-file=filename] -- print track or save it to file
+    upload track <track> -file=filename -- upload track file to device
+    settings show [-file=filename] -- print device settings or save them to file
+    settings set <setting=value>... | -file=filename -- change device settings
+    fmt [--check] <file|directory>... -- rewrite pattern files in canonical form, no device is needed
+    lint <file|directory>... -- warn about steps which won't play the way they're written
+    help [command] -- show this help or help for command, same as <command> --help
+Options:
+    -in=\"name\" -- name of TD-3's midi in port, part of name or regex, found automatically if not specified
+    -out=\"name\" -- name of TD-3's midi out port, part of name or regex, found automatically if not specified
+    -model=name -- treat device as TD-3, TD-3-MO, TD-3-SR or TD-3-AM, for models which are not known yet
+    --device name -- use in/out ports and model of device from [device name] section of config file
+    -file=- -- read from stdin or write to stdout, downloads are written to stdout when -file is not given
+    --quiet -- don't print status messages, only data and errors
+    --lenient -- accept pattern sysex which is longer or has values out of range, for firmware sending it differently
+    --allow-unconfirmed -- send track upload and settings set, which aren't confirmed on device firmware yet
+Slots:
+    2:3B, g2p3b -- group 2 pattern 3B
+    19 -- slot number 1-64, counting 1A..8A, 1B..8B of group 1, then group 2 and so on
+    1-16 -- range of slot numbers
+    2:* -- whole group 2
+    all -- all 64 patterns
+    2 3B -- group and pattern as separate arguments, like older versions had it
 
 Example -- view group 1 pattern 1A:
-    td3pattern 1 1A
+    td3pattern download 1:1A
 Example -- using loopback drivers save group 4 pattern 2B to file
-    td3pattern -in=\"Loopback in 1\" -out=\"Loopback out 1\" 1 2B -file=pattern1-2B.txt
+    td3pattern -in=\"Loopback in 1\" -out=\"Loopback out 1\" download 4:2B -file=pattern4-2B.txt
 Example -- load file and upload it to group 3 pattern 8A
-    td3pattern upload 1 1A -file=confusion-pattern.txt
+    td3pattern upload g3p8a -file=confusion-pattern.txt
+Example -- save group 2 to patterns directory
+    td3pattern backup 2:* -dir=patterns
+Example -- check that every pattern file in patterns directory is formatted
+    td3pattern fmt --check patterns
+Example -- find notes which are never played and other sequencer surprises
+    td3pattern lint patterns
+Example -- copy pattern 1:1A to 2:1A, pattern data goes to stdout, status messages go to stderr
+    td3pattern download 1:1A | td3pattern upload 2:1A -file=-
 ";
 
+// per command help, shown by td3pattern help <command> and td3pattern <command> --help
+const HELP: &[(&str, &str)] = &[
+    (
+        "list",
+        "
+Usage:
+    td3pattern list
+Shows midi in and out ports and TD-3 devices answering on them, with -in/-out options to use them.
+",
+    ),
+    (
+        "info",
+        "
+Usage:
+    td3pattern [options] info
+Shows model, firmware version and which features are supported by it.
+",
+    ),
+    (
+        "download",
+        "
+Usage:
+    td3pattern [options] download <slot>... [-file=filename] [-format=text|syx] [--show-unknown]
+    td3pattern [options] download track <track> [-file=filename]
+Where:
+    <slot> -- 2:3B, g2p3b, 1-64, range like 1-16, 2:* or all, see td3pattern help
+    <track> -- Track 1-7
+    --allow-unconfirmed -- track sysex isn't confirmed on real devices yet, it's not sent without this flag
+    -file=filename -- file for saving pattern(s) or track, printed if not specified, several patterns are
+        saved as bank file with [Group 2 Pattern 3B] line before every pattern
+    -format=text|syx -- format of saved pattern, text or raw sysex
+    --show-unknown -- write Unknown line with sysex bytes nobody knows meaning of, it's written anyway when
+        they differ from usual 00 01, 00 00
+Example -- view group 1 pattern 1A:
+    td3pattern download 1:1A
+Example -- view whole group 2:
+    td3pattern download 2:*
+Example -- save all patterns to one file
+    td3pattern download all -file=bank.txt
+Example -- save track 3 to file
+    td3pattern download track 3 -file=track3.txt
+",
+    ),
+    (
+        "upload",
+        "
+Usage:
+    td3pattern [options] upload <slot> -file=filename
+    td3pattern [options] upload [<slot>...] -file=bank
+    td3pattern [options] upload track <track> -file=filename --allow-unconfirmed
+Where:
+    <slot> -- single slot like 2:3B, g2p3b or 1-64, for bank file or sysex file with several patterns slots to take
+        from it, whole file if none
+    <track> -- Track 1-7
+    -file=filename -- text or sysex pattern file, bank file, sysex file saved from several slots or track file
+Example -- load file and upload it to group 3 pattern 8A
+    td3pattern upload 3:8A -file=confusion-pattern.txt
+Example -- upload only group 1 from bank file
+    td3pattern upload 1:* -file=bank.txt
+",
+    ),
+    (
+        "backup",
+        "
+Usage:
+    td3pattern [options] backup [<slot>...] -dir=directory [--dry-run] [--yes]
+Saves patterns to <directory>/group-N/pattern-XY.txt, all of them if no slots are given.
+Only changed files are written.
+Where:
+    <slot> -- 2:3B, g2p3b, 1-64, range like 1-16, 2:* or all
+    -dir=directory -- could be set by backup-dir in config file
+    --dry-run -- only show what would be written
+    --yes -- write without asking, otherwise the plan is shown and confirmation is asked
+Example -- save group 2 to patterns directory
+    td3pattern backup 2:* -dir=patterns
+",
+    ),
+    (
+        "restore",
+        "
+Usage:
+    td3pattern [options] restore [<slot>...] -dir=directory [--dry-run] [--yes]
+Uploads patterns from <directory>/group-N/pattern-XY.txt, all of them if no slots are given.
+Only slots which differ from files are uploaded, missing files are skipped.
+Where:
+    <slot> -- 2:3B, g2p3b, 1-64, range like 1-16, 2:* or all
+    -dir=directory -- could be set by backup-dir in config file
+    --dry-run -- only show what would be uploaded
+    --yes -- upload without asking, otherwise the plan is shown and confirmation is asked
+",
+    ),
+    (
+        "sync",
+        "
+Usage:
+    td3pattern [options] sync <pull|push> -dir=directory [--dry-run] [--yes]
+Same as backup (pull) or restore (push) of all slots. Files which aren't pattern files are skipped with a warning.
+Where:
+    --dry-run -- only show the plan
+    --yes -- apply the plan without asking
+Example -- mirror whole device to patterns directory
+    td3pattern sync pull -dir=patterns
+",
+    ),
+    (
+        "watch",
+        "
+Usage:
+    td3pattern [options] watch <slot> -file=filename
+Uploads file to slot every time it's saved, parse errors are printed and file is not uploaded.
+Upload which device doesn't accept is reported and retried until it's accepted or the file is changed.
+Example -- edit pattern file and hear changes on group 2 pattern 3A on every save
+    td3pattern watch 2:3A -file=x.txt
+",
+    ),
+    (
+        "settings",
+        "
+Usage:
+    td3pattern [options] settings show [-file=filename]
+    td3pattern [options] settings set <setting=value>... | -file=filename --allow-unconfirmed
+Where:
+    <setting=value> -- midi-in-channel=1-16, midi-out-channel=1-16, clock-source=internal|midi|usb|trigger,
+        accent-velocity=0-127, key-priority=low|high|last, pitch-bend-range=0-12
+Setting ids are not confirmed on real devices yet, so settings set needs --allow-unconfirmed, check the result with
+settings show after it.
+Example -- configure another unit same way as saved one
+    td3pattern settings show -file=settings.txt
+    td3pattern -in=\"TD-3 #2\" -out=\"TD-3 #2\" settings set -file=settings.txt --allow-unconfirmed
+",
+    ),
+    (
+        "fmt",
+        "
+Usage:
+    td3pattern fmt [--check] <file|directory>...
+Rewrites pattern files the way download writes them: aligned columns, canonical names, lines in order.
+Comments are kept. Every pattern of bank file is formatted under [Group 2 Pattern 3B] line. Directories are
+searched recursively for .txt files starting with TD-3 Pattern or slot line of bank file.
+Where:
+    <file|directory> -- pattern or bank file, directory or - to read stdin and print formatted pattern
+    --check -- don't change files, print names of files which aren't formatted and fail if there are any
+Example -- format pattern file
+    td3pattern fmt x.txt
+Example -- fail if anything in patterns directory isn't formatted, e.g. in pre-commit hook
+    td3pattern fmt --check patterns
+",
+    ),
+    (
+        "lint",
+        "
+Usage:
+    td3pattern lint <file|directory>...
+Warns about things which play differently on device than they look in the file. Directories are searched
+recursively for .txt files starting with TD-3 Pattern or slot line of bank file. Fails if there are any warnings.
+Every warning is one line: file:step:code: message, step is empty for pattern as a whole, for bank file
+it's file:[Group 2 Pattern 3B]:step:code: message. Codes:
+    unplayed-note -- note is never reached because notes only advance on played steps, see Sequencer Quirks
+    tie-on-first-step -- tie on step 1 holds the first note, the following notes are played a step later
+    tie-across-loop -- tie on the last active step carries over into step 1
+    slide-into-rest -- note slides into a rest
+    content-beyond-active-steps -- step after active steps isn't empty, it's never played
+    accent-on-rest -- accent is written on a rest step, it's played with the note of that column
+Where:
+    <file|directory> -- pattern or bank file, directory or - to read stdin
+Example -- check pattern before uploading it
+    td3pattern lint x.txt && td3pattern upload 1:1A -file=x.txt
+",
+    ),
+];
+
+fn help(command: Option<&str>) -> Result<(), Box<dyn Error>> {
+    match command {
+        None => write_output(STDIO, format!("{}\n", USAGE).as_bytes())?,
+        Some(command) => match HELP.iter().find(|(name, _)| *name == command) {
+            Some((_, text)) => write_output(STDIO, format!("{}\n", text).as_bytes())?,
+            None => return Err(format!("Unknown command {}, see td3pattern help", command).into()),
+        },
+    }
+    Ok(())
+}
+
 fn main() {
     match run() {
         Ok(_) => (),
-        Err(err) => println!("{}\n{}", err, USAGE),
+        Err(err) => {
+            eprintln!("{}\nSee td3pattern help", err);
+            std::process::exit(1);
+        }
     }
 }
 
 fn run() -> Result<(), Box<dyn Error>> {
     let config = config::get_config()?;
-    let (out_midi, out_port, in_midi, in_port) = open_ports(&(config.out_port), &(config.in_port))?;
-    let (tx, rx) = mpsc::channel::<Vec<u8>>();
-    // midi receive thread is opened by midir
-    let _in_connection = in_midi.connect(
-        &in_port,
-        "midir-read-input",
-        move |_stamp, msg, _| {
-            let _x = tx.send(msg.to_owned());
-        },
-        (),
-    );
-    // main/midi send
-    let mut out_conn = out_midi.connect(&out_port, "").unwrap();
-    match main_processor(&mut out_conn, rx, &config) {
-        Ok(_) => std::process::exit(0),
-        Err(e) => {
-            println!("Error has occured: {}", e);
-            std::process::exit(1);
+    match config.mode {
+        Mode::Help => help(config.help.as_deref()),
+        Mode::Fmt => fmt(&config.paths, config.check),
+        Mode::Lint => lint_files(&config.paths),
+        _ => device_command(&config),
+    }
+}
+
+// list and the rest of commands need midi ports
+#[cfg(feature = "midi")]
+fn device_command(config: &Config) -> Result<(), Box<dyn Error>> {
+    match config.mode {
+        Mode::List => device_commands::list(),
+        _ => device_commands::run(config),
+    }
+}
+
+#[cfg(not(feature = "midi"))]
+fn device_command(config: &Config) -> Result<(), Box<dyn Error>> {
+    Err(format!(
+        "{:?} needs midi, td3pattern is built without midi feature, only fmt and lint are available",
+        config.mode
+    )
+    .into())
+}
+
+// pattern files to format: files given by name and .txt pattern and bank files found in directories
+fn pattern_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let mut entries = read_dir(path)?.map(|entry| Ok(entry?.path())).collect::<Result<Vec<PathBuf>, io::Error>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            pattern_files(&entry, files)?;
+        } else if entry.extension().is_some_and(|ext| ext == "txt") {
+            let text = read_to_string(&entry)?;
+            if is_pattern_file(&text) || is_bank(&text) {
+                files.push(entry);
+            }
         }
-    };
-    /* loop {
-        std::thread::yield_now();
-    } */
+    }
+    Ok(())
 }
 
-fn main_processor(
-    out_conn: &mut midir::MidiOutputConnection,
-    rx: std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
-    config: &Config,
-) -> Result<(), Box<dyn Error>> {
-    let product_name_sysex = send_sysex(out_conn, &rx, "product name", &[0x06])?;
-    // could also be .trim_matches(char::from(0)) for cutting traling 0
-    let product_name = std::str::from_utf8(&product_name_sysex[1..product_name_sysex.len() - 1])?;
-    if product_name != DEFAULT_PORTNAME {
-        return Err(format!("Product name is: '{:?}', expected '{:?}'", product_name, DEFAULT_PORTNAME).into());
+// files given by name and pattern files found in directories given
+fn expand_paths(paths: &[String]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for path in paths.iter().map(PathBuf::from) {
+        match path.is_dir() {
+            true => pattern_files(&path, &mut files)?,
+            false => files.push(path),
+        }
     }
-    let fw_version_sysex = send_sysex(out_conn, &rx, "firmware version", &[0x08, 0x00])?;
-    let fw_version = &fw_version_sysex[2..].iter().map(|x| format!("{}.", x)).collect::<String>();
-    println!("Product Name {}, Firmware version is is {}", product_name, fw_version);
+    Ok(files)
+}
 
-    let ab = if config.ab == 0 { "A" } else { "B" };
-    match config.mode {
-        Mode::Download => {
-            let pattern_sysex = get_pattern(out_conn, &rx, config.group, config.pnum, config.ab)?;
-            let pattern = sysex_to_pattern(&pattern_sysex);
-            let string_pattern = pattern_to_string(&pattern);
-            if config.filename.len() == 0 {
-                println!("Group: {} Pattern: {}{}", config.group, config.pnum, ab);
-                print!("\n{}", string_pattern);
-            } else {
-                let mut file = File::create(&config.filename)?;
-                file.write_all(string_pattern.as_bytes())?;
-                println!("Group {} Pattern: {}{} is saved to {}", config.group, config.pnum, ab, config.filename);
+fn fmt(paths: &[String], check: bool) -> Result<(), Box<dyn Error>> {
+    let files = expand_paths(paths)?;
+    let (mut unformatted, mut failed) = (0, 0);
+    for file in files.iter() {
+        let filename = file.display().to_string();
+        let name = if is_stdio(&filename) { "<stdin>" } else { filename.as_str() };
+        let text = String::from_utf8(read_input(&filename)?)?;
+        let formatted = match is_bank(&text) {
+            true => format_bank(name, &text),
+            false => format_pattern(name, &text),
+        };
+        let formatted = match formatted {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                // every file is checked, errors are reported at the end
+                eprintln!("{}", e);
+                failed += 1;
+                continue;
+            }
+        };
+        if check {
+            if formatted != text {
+                write_output(STDIO, format!("{}\n", name).as_bytes())?;
+                unformatted += 1;
             }
+        } else if is_stdio(&filename) {
+            write_output(&filename, formatted.as_bytes())?;
+        } else if formatted != text {
+            write_output(&filename, formatted.as_bytes())?;
+            status!("File {} is formatted", filename);
         }
-        Mode::Upload => {
-            let string_pattern = read_to_string(&config.filename)?;
-            let pattern = string_to_pattern(string_pattern)?;
-            let pattern_sysex = pattern_to_sysex(&pattern, config.group, config.pnum, config.ab);
-            let _ret = send_sysex(out_conn, &rx, "pattern", pattern_sysex.as_slice());
-            // process _ret???
-            println!("File {} is uploaded to Group {} Pattern: {}{}", config.filename, config.group, config.pnum, ab);
+    }
+    let not_formatted = format!("{} file(s) are not formatted, td3pattern fmt will format them", unformatted);
+    let not_patterns = format!("{} file(s) are not pattern files", failed);
+    match (failed, unformatted) {
+        (0, 0) => Ok(()),
+        (0, _) => Err(not_formatted.into()),
+        (_, 0) => Err(not_patterns.into()),
+        _ => Err(format!("{}, {}", not_patterns, not_formatted).into()),
+    }
+}
+
+fn lint_files(paths: &[String]) -> Result<(), Box<dyn Error>> {
+    let (mut warned, mut failed) = (0, 0);
+    for file in expand_paths(paths)?.iter() {
+        let filename = file.display().to_string();
+        let name = if is_stdio(&filename) { "<stdin>" } else { filename.as_str() };
+        let text = String::from_utf8(read_input(&filename)?)?;
+        // every pattern of bank file is linted, warnings name its slot
+        let patterns = match is_bank(&text) {
+            true => file_to_bank(name, text).map(|bank| {
+                bank.into_iter().map(|entry| (format!("{}:{}", name, slot_header(entry.slot)), entry.pattern)).collect()
+            }),
+            false => file_to_pattern_with_metadata(name, text).map(|(pattern, _)| vec![(name.to_owned(), pattern)]),
+        };
+        let patterns: Vec<(String, Pattern)> = match patterns {
+            Ok(patterns) => patterns,
+            Err(e) => {
+                eprintln!("{}", e);
+                failed += 1;
+                continue;
+            }
+        };
+        for (name, pattern) in patterns.iter() {
+            for warning in lint(pattern).iter() {
+                write_output(STDIO, format!("{}:{}\n", name, warning).as_bytes())?;
+                warned += 1;
+            }
+        }
+    }
+    match (failed, warned) {
+        (0, 0) => Ok(()),
+        (0, _) => Err(format!("{} warning(s)", warned).into()),
+        _ => Err(format!("{} file(s) are not pattern files", failed).into()),
+    }
+}
+
+// no file or -file=- is stdin/stdout
+fn is_stdio(filename: &str) -> bool {
+    filename.is_empty() || filename == STDIO
+}
+
+fn read_input(filename: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    if !is_stdio(filename) {
+        return Ok(read(filename)?);
+    }
+    let mut content = Vec::new();
+    std::io::stdin().read_to_end(&mut content)?;
+    Ok(content)
+}
+
+fn write_output(filename: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+    if is_stdio(filename) {
+        // reader could be gone, e.g. td3pattern help | head, that's not an error
+        match std::io::stdout().write_all(data) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => std::process::exit(0),
+            result => result?,
         }
+    } else {
+        File::create(filename)?.write_all(data)?;
     }
     Ok(())
 }
@@ -0,0 +1,217 @@
+use std::error::Error;
+use std::fs::{create_dir_all, read_to_string, remove_file, File};
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+use crate::config::config_path;
+use crate::pattern::{pattern_to_string_with_metadata, string_to_pattern_with_metadata, Pattern};
+use crate::slot::{slot_path, SlotAddress};
+
+// everything v2 pattern file could say about pattern which device doesn't store
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub tempo: Option<f32>,
+    pub tags: Vec<String>,
+    pub key: Option<String>,
+    // slot pattern was downloaded from
+    pub source: Option<SlotAddress>,
+    // yyyy-mm-dd
+    pub created: Option<String>,
+    // free form, one entry per line
+    pub notes: Vec<String>,
+}
+
+const NAME: &str = "Name";
+const AUTHOR: &str = "Author";
+const TEMPO: &str = "Tempo";
+const TAGS: &str = "Tags";
+const KEY: &str = "Key";
+const SOURCE: &str = "Source";
+const CREATED: &str = "Created";
+const NOTES: &str = "Notes";
+pub const METADATA_FIELDS: &[&str] = &[NAME, AUTHOR, TEMPO, TAGS, KEY, SOURCE, CREATED, NOTES];
+
+// tempo range of TD-3
+const MIN_TEMPO: f32 = 20.0;
+const MAX_TEMPO: f32 = 300.0;
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Default::default()
+    }
+}
+
+// field names are matched ignoring case, canonical name is returned
+pub fn find_metadata_field(key: &str) -> Option<&'static str> {
+    METADATA_FIELDS.iter().find(|field| field.eq_ignore_ascii_case(key.trim())).copied()
+}
+
+// // starts a comment, so second / of // is written as \/ and \ as \\, e.g. https:/\/example.com
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::from("");
+    for c in value.chars() {
+        if c == '\\' || (c == '/' && escaped.ends_with('/')) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// only \/ and \\ are escapes, other backslashes are read as they are
+fn unescape_value(value: &str) -> String {
+    let mut unescaped = String::from("");
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next @ ('/' | '\\'))) => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+pub fn metadata_to_string(metadata: &Metadata) -> String {
+    let mut metadata_str = String::from("");
+    let mut field = |name: &str, value: &str| metadata_str.push_str(&format!("{}: {}\n", name, escape_value(value)));
+    if let Some(name) = &metadata.name {
+        field(NAME, name);
+    }
+    if let Some(author) = &metadata.author {
+        field(AUTHOR, author);
+    }
+    if let Some(tempo) = metadata.tempo {
+        field(TEMPO, &tempo.to_string());
+    }
+    if !metadata.tags.is_empty() {
+        field(TAGS, &metadata.tags.join(", "));
+    }
+    if let Some(key) = &metadata.key {
+        field(KEY, key);
+    }
+    if let Some(source) = metadata.source {
+        field(SOURCE, &source.to_string());
+    }
+    if let Some(created) = &metadata.created {
+        field(CREATED, created);
+    }
+    for note in metadata.notes.iter() {
+        field(NOTES, note);
+    }
+    metadata_str
+}
+
+// "Field: value" line of v2 header, false if line isn't a metadata field
+pub fn parse_metadata_line(metadata: &mut Metadata, line: &str) -> Result<bool, Box<dyn Error>> {
+    let (field, value) =
+        match line.split_once(':').and_then(|(field, value)| Some((find_metadata_field(field)?, value))) {
+            Some((field, value)) => (field, unescape_value(value.trim())),
+            None => return Ok(false),
+        };
+    let value = value.as_str();
+    match field {
+        NAME => metadata.name = Some(value.to_owned()),
+        AUTHOR => metadata.author = Some(value.to_owned()),
+        TEMPO => match value.parse::<f32>() {
+            Ok(tempo) if (MIN_TEMPO..=MAX_TEMPO).contains(&tempo) => metadata.tempo = Some(tempo),
+            _ => return Err(format!("Tempo should be from {} to {}, read: {}", MIN_TEMPO, MAX_TEMPO, value).into()),
+        },
+        TAGS => {
+            metadata.tags = value.split(',').map(|t| t.trim().to_owned()).filter(|t| !t.is_empty()).collect();
+        }
+        KEY => metadata.key = Some(value.to_owned()),
+        SOURCE => match value.parse::<SlotAddress>() {
+            Ok(slot) => metadata.source = Some(slot),
+            _ => return Err(format!("Source should be single slot like 1:2A, read: {}", value).into()),
+        },
+        CREATED => match scan_fmt::scan_fmt!(value, "{d}-{d}-{d}", u16, u8, u8) {
+            Ok((_, 1..=12, 1..=31)) => metadata.created = Some(value.to_owned()),
+            _ => return Err(format!("Created should be date like 2023-01-31, read: {}", value).into()),
+        },
+        NOTES => metadata.notes.push(value.to_owned()),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+// device can't store metadata, so uploaded files are remembered next to config file
+pub fn sidecar_dir() -> Option<PathBuf> {
+    config_path().and_then(|path| path.parent().map(|dir| dir.join("metadata")))
+}
+
+fn sidecar_path(slot: SlotAddress) -> Option<PathBuf> {
+    sidecar_dir().map(|dir| slot_path(&dir, slot))
+}
+
+// metadata for pattern just downloaded from slot, remembered one if pattern wasn't changed on device since upload,
+// otherwise none, so plain downloads stay in v1 format and don't change from day to day
+pub fn download_metadata(pattern: &Pattern, slot: SlotAddress) -> Metadata {
+    sidecar_path(slot)
+        .and_then(|path| read_to_string(path).ok())
+        .and_then(|content| string_to_pattern_with_metadata(content).ok())
+        .filter(|(sidecar, _)| sidecar == pattern)
+        .map_or(Default::default(), |(_, metadata)| Metadata { source: Some(slot), ..metadata })
+}
+
+// called after upload, failure to remember isn't a reason to fail upload
+pub fn remember_metadata(pattern: &Pattern, metadata: &Metadata, slot: SlotAddress) {
+    let path = match sidecar_path(slot) {
+        Some(path) => path,
+        None => return,
+    };
+    let result = if metadata.is_empty() {
+        match path.exists() {
+            true => remove_file(&path),
+            false => Ok(()),
+        }
+    } else {
+        path.parent()
+            .map_or(Ok(()), create_dir_all)
+            .and_then(|_| File::create(&path)?.write_all(pattern_to_string_with_metadata(pattern, metadata).as_bytes()))
+    };
+    if let Err(e) = result {
+        eprintln!("Warning: metadata is not saved to {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(lines: &[&str]) -> Metadata {
+        let mut metadata = Metadata::default();
+        for line in lines {
+            assert!(parse_metadata_line(&mut metadata, line).unwrap(), "{}", line);
+        }
+        metadata
+    }
+
+    #[test]
+    fn field_names_ignore_case() {
+        let metadata = parse(&["name: Confusion", "AUTHOR: beholder", "tEmPo: 128", "notes: one", "Notes: two"]);
+        assert_eq!(metadata.name.as_deref(), Some("Confusion"));
+        assert_eq!(metadata.author.as_deref(), Some("beholder"));
+        assert_eq!(metadata.tempo, Some(128.0));
+        assert_eq!(metadata.notes, vec!["one", "two"]);
+        assert!(!parse_metadata_line(&mut Metadata::default(), "Nam: Confusion").unwrap());
+    }
+
+    #[test]
+    fn slashes_survive_round_trip() {
+        let values = ["https://example.com", "a///b", "c:\\dir\\", "\\/", "//", "x/ /y"];
+        for value in values {
+            let metadata = Metadata { notes: vec![value.to_owned()], ..Default::default() };
+            let text = metadata_to_string(&metadata);
+            assert!(!text.contains("//"), "{}", text);
+            let line = text.trim_end();
+            assert_eq!(parse(&[line]), metadata, "{}", line);
+        }
+        // backslashes of hand written files which aren't escapes are kept
+        assert_eq!(parse(&["Notes: c:\\dir\\file"]).notes, vec!["c:\\dir\\file"]);
+    }
+}
@@ -1,63 +1,180 @@
 use std::error::Error;
 
+use std::sync::mpsc;
+use std::time::Duration;
+
+extern crate regex;
+use regex::Regex;
+
+use crate::config::DEFAULT_PORTNAME;
+use crate::device::{check_response, default_profile, parse_product_name, Profile, PROFILES, SYX_POST};
+use crate::slot::SlotAddress;
+
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+const PRODUCT_NAME: u8 = 0x06;
+
+// device answered to product name request
+pub struct Device {
+    pub product_name: String,
+    pub out_port: String,
+    pub in_port: String,
+}
+
+pub fn port_names() -> Result<(Vec<String>, Vec<String>), Box<dyn Error>> {
+    let out_midi = midir::MidiOutput::new("")?;
+    let out_names = out_midi.ports().iter().map(|p| out_midi.port_name(p).unwrap_or_default()).collect();
+    let in_midi = midir::MidiInput::new("")?;
+    let in_names = in_midi.ports().iter().map(|p| in_midi.port_name(p).unwrap_or_default()).collect();
+    Ok((out_names, in_names))
+}
+
+// exact name first, then substring and regex, e.g. "TD-3" matches "TD-3:TD-3 MIDI 1 24:0" on Linux ALSA
+fn match_port(names: &[String], wanted: &str) -> Option<usize> {
+    if let Some(n) = names.iter().position(|name| name == wanted) {
+        return Some(n);
+    }
+    if let Some(n) = names.iter().position(|name| name.contains(wanted)) {
+        return Some(n);
+    }
+    match Regex::new(wanted) {
+        Ok(re) => names.iter().position(|name| re.is_match(name)),
+        Err(_) => None,
+    }
+}
+
+// sends product name request to every output port and listens on every input port, so in/out pairs could be
+// matched even when they're named differently
+pub fn probe_ports() -> Result<Vec<Device>, Box<dyn Error>> {
+    let (out_names, in_names) = port_names()?;
+    let (tx, rx) = mpsc::channel::<(usize, Vec<u8>)>();
+    let mut in_connections = Vec::new();
+    for n in 0..in_names.len() {
+        let mut in_midi = midir::MidiInput::new("")?;
+        in_midi.ignore(midir::Ignore::TimeAndActiveSense);
+        let port = match in_midi.ports().get(n) {
+            Some(p) => p.clone(),
+            None => continue,
+        };
+        let tx = tx.clone();
+        // ports which couldn't be opened (e.g. busy) are skipped
+        if let Ok(conn) = in_midi.connect(
+            &port,
+            "midir-probe-input",
+            move |_stamp, msg, _| {
+                let _x = tx.send((n, msg.to_owned()));
+            },
+            (),
+        ) {
+            in_connections.push(conn);
+        }
+    }
+    let mut devices = Vec::new();
+    for (n, out_name) in out_names.iter().enumerate() {
+        let out_midi = midir::MidiOutput::new("")?;
+        let port = match out_midi.ports().get(n) {
+            Some(p) => p.clone(),
+            None => continue,
+        };
+        let mut out_conn = match out_midi.connect(&port, "") {
+            Ok(conn) => conn,
+            Err(_) => continue,
+        };
+        // whatever other devices sent meanwhile isn't an answer
+        while rx.try_recv().is_ok() {}
+        // model isn't known yet, so TD-3 header is used
+        let header = default_profile().header;
+        if out_conn.send(header).and(out_conn.send(&[PRODUCT_NAME])).and(out_conn.send(SYX_POST)).is_err() {
+            continue;
+        }
+        while let Ok((in_n, m)) = rx.recv_timeout(PROBE_TIMEOUT) {
+            if let Ok(product_name) =
+                check_response(&m, header, "product name").and_then(|msg| parse_product_name(&msg))
+            {
+                devices.push(Device {
+                    product_name,
+                    out_port: out_name.to_owned(),
+                    in_port: in_names[in_n].to_owned(),
+                });
+                break;
+            }
+        }
+    }
+    Ok(devices)
+}
+
+// model is profile set with -model, when it's not set any known model is looked for
 pub fn open_ports(
     out_port: &String,
     in_port: &String,
+    model: Option<&Profile>,
 ) -> Result<(midir::MidiOutput, midir::MidiOutputPort, midir::MidiInput, midir::MidiInputPort), Box<dyn Error>> {
-    // Out
-    let out_midi = midir::MidiOutput::new("").expect("Failed create MidiOutput");
-    let ports = out_midi.ports();
-    let out_port = match ports.iter().find(|p| out_midi.port_name(p).unwrap() == *out_port) {
-        Some(p) => p,
-        None => {
-            let port_names = ports.iter().map(|p| out_midi.port_name(p).expect("")).collect::<Vec<String>>().join(", ");
-            return Err(format!("Output port \"{}\" is not found, available ports: {}", out_port, port_names).into());
+    let (out_names, in_names) = port_names()?;
+    let exact = (out_names.iter().position(|p| p == out_port), in_names.iter().position(|p| p == in_port));
+    let (out_n, in_n) = match exact {
+        (Some(out_n), Some(in_n)) => (out_n, in_n),
+        // ports aren't specified, so asking everybody who is TD-3 or its relative
+        _ if out_port == DEFAULT_PORTNAME && in_port == DEFAULT_PORTNAME => {
+            let devices = probe_ports()?;
+            let device = match model {
+                // model is overriden for unknown device, so the first one answered is taken
+                Some(_) => devices.first(),
+                None => devices.iter().find(|d| PROFILES.iter().any(|p| p.model == d.product_name)),
+            };
+            let port_name = model.unwrap_or(default_profile()).port_name;
+            match device {
+                // ports could change between probing and opening them
+                Some(d) => match (match_port(&out_names, &d.out_port), match_port(&in_names, &d.in_port)) {
+                    (Some(out_n), Some(in_n)) => (out_n, in_n),
+                    _ => return Err(format!("Ports of {} have changed, please try again", d.product_name).into()),
+                },
+                None => (find_port(&out_names, port_name, "Output")?, find_port(&in_names, port_name, "Input")?),
+            }
         }
+        _ => (find_port(&out_names, out_port, "Output")?, find_port(&in_names, in_port, "Input")?),
+    };
+    // Out
+    let out_midi = midir::MidiOutput::new("")?;
+    let out_port = match out_midi.ports().get(out_n) {
+        Some(p) => p.to_owned(),
+        None => return Err("Output ports have changed, please try again".into()),
     };
     // In
-    let mut in_midi = midir::MidiInput::new("").expect("Failed create MidiOutput");
+    let mut in_midi = midir::MidiInput::new("")?;
     in_midi.ignore(midir::Ignore::TimeAndActiveSense);
-    let ports = in_midi.ports();
-    let in_port = match ports.iter().find(|p| in_midi.port_name(p).unwrap() == *in_port) {
-        Some(p) => p,
-        None => {
-            let port_names = ports.iter().map(|p| in_midi.port_name(p).expect("")).collect::<Vec<String>>().join(", ");
-            return Err(format!("Input port \"{}\" is not found, available ports: {}", in_port, port_names).into());
-        }
+    let in_port = match in_midi.ports().get(in_n) {
+        Some(p) => p.to_owned(),
+        None => return Err("Input ports have changed, please try again".into()),
     };
-    Ok((out_midi, out_port.to_owned(), in_midi, in_port.to_owned()))
+    Ok((out_midi, out_port, in_midi, in_port))
 }
 
-const SYX_PRE: &'static [u8] = &[0xF0, 0x00, 0x20, 0x32, 0x00, 0x01, 0x0A];
-const SYX_POST: &'static [u8] = &[0xF7];
+fn find_port(names: &[String], wanted: &str, desc: &str) -> Result<usize, Box<dyn Error>> {
+    match match_port(names, wanted) {
+        Some(n) => Ok(n),
+        None => Err(format!("{} port \"{}\" is not found, available ports: {}", desc, wanted, names.join(", ")).into()),
+    }
+}
 
 pub fn send_sysex(
     out_conn: &mut midir::MidiOutputConnection,
     rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    profile: &Profile,
     desc: &str,
     data_smsg: &[u8],
 ) -> Result<std::vec::Vec<u8>, Box<dyn Error>> {
     if cfg!(debug_assertions) {
-        println!(">> Requesting {}, data part of message = {:02x?}", desc, data_smsg);
+        eprintln!(">> Requesting {}, data part of message = {:02x?}", desc, data_smsg);
     }
-    out_conn.send(SYX_PRE).unwrap();
-    out_conn.send(&data_smsg).unwrap();
+    out_conn.send(profile.header).unwrap();
+    out_conn.send(data_smsg).unwrap();
     out_conn.send(SYX_POST).unwrap();
     let rmsg = rx.recv(); // std::vec::Vec<u8>
     match rmsg {
         Ok(m) => {
             if cfg!(debug_assertions) {
-                println!("<< Response ({}b) {:02x?}", m.len(), m);
-            }
-            // Match head and tail
-            if m.len() < SYX_PRE.len() + SYX_POST.len() + 1
-                || (&SYX_PRE)[0..SYX_PRE.len()].iter().zip(&m[0..SYX_PRE.len()]).filter(|&(a, b)| a != b).count() > 0
-                || *m.last().unwrap() != SYX_POST[0]
-            {
-                return Err(format!("Response for {} has wrong size", desc).into());
-            } else {
-                return Ok((&m[SYX_PRE.len()..m.len() - 1]).to_owned());
+                eprintln!("<< Response ({}b) {:02x?}", m.len(), m);
             }
+            check_response(&m, profile.header, desc)
         }
         Err(_) => Err(format!("No response for {} has been received", desc).into()),
     }
@@ -66,17 +183,28 @@ pub fn send_sysex(
 pub fn get_pattern(
     out_conn: &mut midir::MidiOutputConnection,
     rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
-    group: u8,
-    pnum: u8,
-    ab: u8,
+    profile: &Profile,
+    slot: SlotAddress,
+) -> Result<std::vec::Vec<u8>, Box<dyn Error>> {
+    let desc = format!("Pattern Group {} Pattern {}{}", slot.group() + 1, slot.number() + 1, slot.side());
+    let [group, pattern] = slot.to_sysex();
+    let msg = send_sysex(out_conn, rx, profile, &desc, &[0x77, group, pattern])?;
+    // longer response is left for lenient decoding
+    if msg.len() < profile.pattern_len {
+        return Err(format!("Response for {} is {} bytes, expected {}", desc, msg.len(), profile.pattern_len).into());
+    }
+    Ok(msg)
+}
+
+pub fn get_track(
+    out_conn: &mut midir::MidiOutputConnection,
+    rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    profile: &Profile,
+    tnum: u8,
 ) -> Result<std::vec::Vec<u8>, Box<dyn Error>> {
-    if group > 3 {
-        return Err("Invalid group specified".into());
-    } else if pnum > 7 {
-        return Err("Invalid pattern specified".into());
-    } else if ab > 1 {
-        return Err("Invalid AB specified".into());
+    if tnum > 6 {
+        return Err("Invalid track specified".into());
     }
-    let desc = format!("Pattern Group {} Pattern {}{}", group + 1, pnum + 1, if ab == 0 { "a" } else { "b" });
-    send_sysex(out_conn, rx, &desc, &[0x77, group, pnum + (ab << 3)])
+    let desc = format!("Track {}", tnum + 1);
+    send_sysex(out_conn, rx, profile, &desc, &[0x7A, tnum])
 }
@@ -1,22 +1,229 @@
 use std::convert::TryInto;
 use std::error::Error;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::slice;
 use std::str::FromStr;
 use std::str::Lines;
 
-extern crate scan_fmt;
-use scan_fmt::scan_fmt;
-
+use crate::diagnostic::{did_you_mean, Diagnostics};
+use crate::metadata::{metadata_to_string, parse_metadata_line, Metadata, METADATA_FIELDS};
+use crate::slot::SlotAddress;
 use crate::step;
+use crate::step::{Pitch, HIGHEST_OCTAVE, LOWEST_OCTAVE};
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Pattern {
-    triplet: bool,
-    active_steps: u8,
-    step: [step::Step; 16],
+    pub(crate) triplet: bool,
+    pub(crate) active_steps: u8,
+    pub(crate) step: [step::Step; 16],
+    // sysex bytes nobody knows meaning of, see Sysex in README.md, sent back as they came from device
+    pub(crate) unknown1: [u8; 2],
+    pub(crate) unknown2: [u8; 2],
 }
 
+// what device sends for every pattern seen so far
+const UNKNOWN1: [u8; 2] = [0x00, 0x01];
+const UNKNOWN2: [u8; 2] = [0x00, 0x00];
+
 impl Default for Pattern {
     fn default() -> Pattern {
-        Pattern { triplet: false, active_steps: 1, step: [step::Step { ..Default::default() }; 16] }
+        Pattern {
+            triplet: false,
+            active_steps: 1,
+            step: [Default::default(); 16],
+            unknown1: UNKNOWN1,
+            unknown2: UNKNOWN2,
+        }
+    }
+}
+
+pub const STEPS: usize = 16;
+
+// step is 0-based, step itself is always valid, its pitch is checked when it's set
+fn check_step(n: usize) -> Result<(), Box<dyn Error>> {
+    if n >= STEPS {
+        return Err(format!("Step should be from 1 to {}, read: {}", STEPS, n + 1).into());
+    }
+    Ok(())
+}
+
+fn check_active_steps(active_steps: u8) -> Result<(), Box<dyn Error>> {
+    match active_steps as usize {
+        1..=STEPS => Ok(()),
+        _ => Err(format!("{} should be from 1 to {}, read: {}", ACTIVE_STEPS, STEPS, active_steps).into()),
+    }
+}
+
+// sysex data bytes are 7 bit
+fn check_unknown(unknown1: [u8; 2], unknown2: [u8; 2]) -> Result<(), Box<dyn Error>> {
+    match unknown1.iter().chain(unknown2.iter()).find(|b| **b > 0x7F) {
+        Some(b) => Err(format!("{} bytes should be from 00 to 7f, read: {:02x}", UNKNOWN, b).into()),
+        None => Ok(()),
+    }
+}
+
+impl Pattern {
+    // single active step with C, like empty pattern on device
+    pub fn new() -> Pattern {
+        Default::default()
+    }
+
+    // steps after active ones are kept, device keeps them too
+    pub fn from_steps(steps: [step::Step; 16], active_steps: u8, triplet: bool) -> Result<Pattern, Box<dyn Error>> {
+        Pattern::builder().steps(steps).active_steps(active_steps).triplet(triplet).build()
+    }
+
+    pub fn builder() -> PatternBuilder {
+        Default::default()
+    }
+
+    pub fn triplet(&self) -> bool {
+        self.triplet
+    }
+
+    pub fn set_triplet(&mut self, triplet: bool) {
+        self.triplet = triplet;
+    }
+
+    pub fn active_steps(&self) -> u8 {
+        self.active_steps
+    }
+
+    pub fn set_active_steps(&mut self, active_steps: u8) -> Result<(), Box<dyn Error>> {
+        check_active_steps(active_steps)?;
+        self.active_steps = active_steps;
+        Ok(())
+    }
+
+    // all 16 steps, active or not
+    pub fn steps(&self) -> &[step::Step; 16] {
+        &self.step
+    }
+
+    // 0-based
+    pub fn step(&self, n: usize) -> Option<&step::Step> {
+        self.step.get(n)
+    }
+
+    pub fn set_step(&mut self, n: usize, step: step::Step) -> Result<(), Box<dyn Error>> {
+        check_step(n)?;
+        self.step[n] = step;
+        Ok(())
+    }
+
+    // raw sysex bytes, see Sysex in README.md
+    pub fn unknown(&self) -> ([u8; 2], [u8; 2]) {
+        (self.unknown1, self.unknown2)
+    }
+
+    pub fn set_unknown(&mut self, unknown1: [u8; 2], unknown2: [u8; 2]) -> Result<(), Box<dyn Error>> {
+        check_unknown(unknown1, unknown2)?;
+        (self.unknown1, self.unknown2) = (unknown1, unknown2);
+        Ok(())
+    }
+
+    // active steps in play order
+    pub fn iter(&self) -> slice::Iter<'_, step::Step> {
+        self.step[0..self.active_steps as usize].iter()
+    }
+
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, step::Step> {
+        self.step[0..self.active_steps as usize].iter_mut()
+    }
+}
+
+// any of 16 steps, 0-based, panics like slice does for step out of range
+impl Index<usize> for Pattern {
+    type Output = step::Step;
+    fn index(&self, n: usize) -> &step::Step {
+        &self.step[n]
+    }
+}
+
+// step keeps its note in range itself, so mutable steps can't break writers
+impl IndexMut<usize> for Pattern {
+    fn index_mut(&mut self, n: usize) -> &mut step::Step {
+        &mut self.step[n]
+    }
+}
+
+impl<'a> IntoIterator for &'a Pattern {
+    type Item = &'a step::Step;
+    type IntoIter = slice::Iter<'a, step::Step>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Pattern {
+    type Item = &'a mut step::Step;
+    type IntoIter = slice::IterMut<'a, step::Step>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+// same as pattern_to_string
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", pattern_to_string(self))
+    }
+}
+
+// everything is checked in build, steps which aren't given are C, without active_steps every given step is active
+#[derive(Debug, Clone, Default)]
+pub struct PatternBuilder {
+    triplet: bool,
+    active_steps: Option<u8>,
+    steps: Vec<step::Step>,
+    unknown: Option<([u8; 2], [u8; 2])>,
+}
+
+impl PatternBuilder {
+    pub fn new() -> PatternBuilder {
+        Default::default()
+    }
+
+    pub fn triplet(mut self, triplet: bool) -> PatternBuilder {
+        self.triplet = triplet;
+        self
+    }
+
+    pub fn active_steps(mut self, active_steps: u8) -> PatternBuilder {
+        self.active_steps = Some(active_steps);
+        self
+    }
+
+    // next step
+    pub fn step(mut self, step: step::Step) -> PatternBuilder {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn steps(mut self, steps: impl IntoIterator<Item = step::Step>) -> PatternBuilder {
+        self.steps.extend(steps);
+        self
+    }
+
+    pub fn unknown(mut self, unknown1: [u8; 2], unknown2: [u8; 2]) -> PatternBuilder {
+        self.unknown = Some((unknown1, unknown2));
+        self
+    }
+
+    pub fn build(self) -> Result<Pattern, Box<dyn Error>> {
+        if self.steps.len() > STEPS {
+            return Err(format!("Pattern should have up to {} steps, {} given", STEPS, self.steps.len()).into());
+        }
+        let mut pattern = Pattern { triplet: self.triplet, ..Default::default() };
+        for (n, step) in self.steps.iter().enumerate() {
+            pattern.set_step(n, *step)?;
+        }
+        pattern.set_active_steps(self.active_steps.unwrap_or(self.steps.len().max(1) as u8))?;
+        if let Some((unknown1, unknown2)) = self.unknown {
+            pattern.set_unknown(unknown1, unknown2)?;
+        }
+        Ok(pattern)
     }
 }
 
@@ -30,59 +237,181 @@ macro_rules! four_u8_to_u16 {
     };
 }
 
-pub fn sysex_to_pattern(msg: &[u8]) -> Pattern {
+// pattern sysex size without header and F7
+pub const PATTERN_SYSEX_LEN: usize = 0x73;
+const PATTERN_ID: u8 = 0x78;
+
+// how forgiving decoding of pattern sysex is
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Decode {
+    // exact length, every value in range
+    Strict,
+    // for firmware which sends slightly different layout: longer message is cut, nibbles are masked, non zero flags
+    // are on, notes out of range are C and active steps out of range are 1 or 16
+    Lenient,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SysexError {
+    Length { expected: usize, actual: usize },
+    MessageId(u8),
+    // group and pattern bytes device has sent for pattern asked from slot
+    Slot { expected: SlotAddress, group: u8, pattern: u8 },
+    Value { offset: usize, value: u8, field: &'static str },
+}
+
+impl fmt::Display for SysexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SysexError::Length { expected, actual } => {
+                write!(f, "Pattern sysex is {} bytes, expected {}", actual, expected)
+            }
+            SysexError::MessageId(id) => {
+                write!(f, "Pattern sysex has message id {:02x}, expected {:02x}", id, PATTERN_ID)
+            }
+            SysexError::Slot { expected, group, pattern } => write!(
+                f,
+                "Pattern sysex is for group/pattern bytes {:02x} {:02x}, expected {}",
+                group, pattern, expected
+            ),
+            SysexError::Value { offset, value, field } => {
+                write!(f, "Pattern sysex has {} out of range: {:02x} at offset {:#04x}", field, value, offset)
+            }
+        }
+    }
+}
+
+impl Error for SysexError {}
+
+// strict decoding of pattern sysex, e.g. read from .syx file
+pub fn sysex_to_pattern(msg: &[u8]) -> Result<Pattern, SysexError> {
+    decode_pattern(msg, None, Decode::Strict)
+}
+
+// slot is checked against group/pattern bytes, it's the slot pattern was asked from
+pub fn decode_pattern(msg: &[u8], slot: Option<SlotAddress>, decode: Decode) -> Result<Pattern, SysexError> {
+    let strict = decode == Decode::Strict;
+    if msg.len() < PATTERN_SYSEX_LEN || (strict && msg.len() != PATTERN_SYSEX_LEN) {
+        return Err(SysexError::Length { expected: PATTERN_SYSEX_LEN, actual: msg.len() });
+    }
+    if msg[0] != PATTERN_ID {
+        return Err(SysexError::MessageId(msg[0]));
+    }
+    if let Some(slot) = slot {
+        if slot.to_sysex() != msg[1..3] {
+            return Err(SysexError::Slot { expected: slot, group: msg[1], pattern: msg[2] });
+        }
+    }
+    let mut msg = msg[0..PATTERN_SYSEX_LEN].to_vec();
+    // data is sent in nibbles, flags as 00 01, unknown bytes are kept as they are if they are midi data bytes
+    let ranges: &[(std::ops::Range<usize>, u8, &str)] = &[
+        (0x03..0x05, 0x7F, "unknown"),
+        (0x05..0x25, 0x0F, "note"),
+        (0x25..0x45, 0x01, "accent"),
+        (0x45..0x65, 0x01, "slide"),
+        (0x65..0x67, 0x01, "triplet"),
+        (0x67..0x69, 0x0F, "active steps"),
+        (0x69..0x6B, 0x7F, "unknown"),
+        (0x6B..0x73, 0x0F, "tie/rest"),
+    ];
+    for (range, max, field) in ranges.iter() {
+        for offset in range.clone() {
+            let value = msg[offset];
+            match (value > *max, strict) {
+                (false, _) => (),
+                (true, true) => return Err(SysexError::Value { offset, value, field }),
+                (true, false) => msg[offset] = if *max == 0x01 { 0x01 } else { value & max },
+            }
+        }
+    }
+    let active_steps = (msg[0x67] << 4) + msg[0x68];
+    if strict && !(1..=16).contains(&active_steps) {
+        return Err(SysexError::Value { offset: 0x67, value: active_steps, field: "active steps" });
+    }
+    // pattern should still be written to file and read back
+    let active_steps = active_steps.clamp(1, 16);
     let tienum = four_u8_to_u16!(msg, 0x6B);
     let restnum = four_u8_to_u16!(msg, 0x6F);
     let mut step: [step::Step; 16] = Default::default();
-    for n in 0..16 {
-        let s = &mut step[n];
-        // whether it's upper c (i.e. last bit is 1)
+    for (n, s) in step.iter_mut().enumerate() {
+        // note byte is device note index, not midi note: 12 is C an octave down, 24 is C, 48 is C^ up,
+        // it's Pitch::midi_note - 24, 0x80 is upper C flag
         let dn = n * 2;
         let note = (msg[0x06 + dn] + (msg[0x05 + dn] << 4)) & 0x7f;
         let mut upperc = (msg[0x05 + dn] & 0x8) >> 3;
-        // also there is case, when it's done via midi number for upper C
+        // whether it's upper c (i.e. last bit is 1), also there is case, when it's done via note index of C^ up
         if note == 0x30 {
-            upperc = 01;
+            upperc = 1;
+        }
+        // DN C .. UP C^, upper C flag goes only with C an octave above DN C
+        if !(12..=48).contains(&note) || (upperc == 1 && (note == 12 || !note.is_multiple_of(12))) {
+            if strict {
+                return Err(SysexError::Value {
+                    offset: 0x05 + dn,
+                    value: msg[0x06 + dn] + (msg[0x05 + dn] << 4),
+                    field: "note",
+                });
+            }
+        } else {
+            // we're actually using 13 notes like it's on td-3
+            s.set_note(note % 12 + upperc * 12, (note / 12 - 1 - upperc).try_into().unwrap()).unwrap();
         }
-        // we're actually using 13 notes like it's on td-3
-        s.note = note % 12 + upperc * 12;
-        s.transpose = (note / 12 - 1 - upperc).try_into().unwrap();
         s.accent = msg[0x26 + dn].try_into().unwrap();
         s.slide = msg[0x46 + dn].try_into().unwrap();
         // rest is more important than tie in sequencor
-        s.time = (((&tienum & (1 as u16) << n) >> n) + (((&restnum & (1 as u16) << n) >> n) << 1)).try_into().unwrap();
+        s.time = (((tienum & 1u16 << n) >> n) + (((restnum & 1u16 << n) >> n) << 1)).try_into().unwrap();
         if cfg!(debug_assertions) {
-            println!(
+            eprintln!(
                 "{:02}: raw nt {:03} - (c^={:1}) mid {:02} - nt {:02} tr {:?}, ac {}, sl {}, raw t {} r {} - t/r {:?}",
                 n,
                 (msg[0x06 + dn] + (msg[0x05 + dn] << 4)),
                 upperc,
                 note,
-                s.note,
-                s.transpose,
+                s.note(),
+                s.transpose(),
                 s.slide as u16,
                 s.accent as u16,
-                (&tienum & (1 as u16) << n) >> n,
-                (&restnum & (1 as u16) << n) >> n,
+                (tienum & 1u16 << n) >> n,
+                (restnum & 1u16 << n) >> n,
                 s.time
             );
         }
     }
-    Pattern { triplet: msg[0x66] == 1, active_steps: (msg[0x67] << 4) + msg[0x68], step }
+    Ok(Pattern {
+        triplet: msg[0x66] == 1,
+        active_steps,
+        step,
+        unknown1: [msg[0x03], msg[0x04]],
+        unknown2: [msg[0x69], msg[0x6A]],
+    })
 }
 
-const TD3_PATTERN: &'static str = "TD-3 Pattern";
-const ACTIVE_STEPS: &'static str = "Active Steps";
-const TRIPLET: &'static str = "Triplet Time";
-const NOTE_S: &'static str = "Note:      ";
-const TRANSPOSE_S: &'static str = "Transpose: ";
-const ACCENT_S: &'static str = "Accent:    ";
-const SLIDE_S: &'static str = "Slide:     ";
-const TIME: &'static str = "Tie/Rest:  ";
+const TD3_PATTERN: &str = "TD-3 Pattern";
+// v2 has metadata fields between header and Active Steps
+const TD3_PATTERN_V2: &str = "TD-3 Pattern v2";
+const ACTIVE_STEPS: &str = "Active Steps";
+const TRIPLET: &str = "Triplet Time";
+const NOTE_S: &str = "Note:      ";
+const TRANSPOSE_S: &str = "Transpose: ";
+const ACCENT_S: &str = "Accent:    ";
+const SLIDE_S: &str = "Slide:     ";
+const TIME: &str = "Tie/Rest:  ";
+const UNKNOWN: &str = "Unknown";
 
-const NOTE: &'static [&str] = &["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B", "C^"];
+pub const NOTE: &[&str] = &["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B", "C^"];
 
+// pattern only, written as v1 so it could be compared and read by older versions
 pub fn pattern_to_string(pattern: &Pattern) -> String {
+    pattern_to_string_with_metadata(pattern, &Default::default())
+}
+
+// Unknown line is written only when bytes differ from usual ones, so files stay the same for most patterns
+pub fn pattern_to_string_with_metadata(pattern: &Pattern, metadata: &Metadata) -> String {
+    pattern_to_string_with_unknown(pattern, metadata, false)
+}
+
+// unknown forces Unknown line even for usual bytes
+pub fn pattern_to_string_with_unknown(pattern: &Pattern, metadata: &Metadata, unknown: bool) -> String {
     let mut sep = String::from("");
     let mut num = String::from("// Step:   ");
     let mut note = String::from(NOTE_S);
@@ -96,8 +425,8 @@ pub fn pattern_to_string(pattern: &Pattern) -> String {
         }
         let s = &pattern.step[i];
         num.push_str(&format!("{} {:02?}", sep, i + 1));
-        note.push_str(&format!("{} {:2}", sep, NOTE[s.note as usize]));
-        transpose.push_str(&format!("{} {:2?}", sep, s.transpose));
+        note.push_str(&format!("{} {:2}", sep, NOTE[s.note() as usize]));
+        transpose.push_str(&format!("{} {:2?}", sep, s.transpose()));
         accent.push_str(&format!("{} {:2?}", sep, s.accent));
         slide.push_str(&format!("{} {:2?}", sep, s.slide));
         time.push_str(&format!("{} {:2?}", sep, s.time));
@@ -109,8 +438,12 @@ pub fn pattern_to_string(pattern: &Pattern) -> String {
     slide.push_str("  //   -SL\n");
     time.push_str("  //   -TI-RE\n");
 
-    let mut pattern_str = String::from(TD3_PATTERN);
+    let mut pattern_str = String::from(if metadata.is_empty() { TD3_PATTERN } else { TD3_PATTERN_V2 });
     pattern_str.push('\n');
+    if !metadata.is_empty() {
+        pattern_str.push_str(&metadata_to_string(metadata));
+        pattern_str.push('\n');
+    }
     pattern_str.push_str(&format!(
         "{}: {}, {}: {}\n",
         ACTIVE_STEPS,
@@ -118,6 +451,15 @@ pub fn pattern_to_string(pattern: &Pattern) -> String {
         TRIPLET,
         if pattern.triplet { "On" } else { "Off" }
     ));
+    if unknown || pattern.unknown1 != UNKNOWN1 || pattern.unknown2 != UNKNOWN2 {
+        let bytes = |b: [u8; 2]| format!("{:02x} {:02x}", b[0], b[1]);
+        pattern_str.push_str(&format!(
+            "{}: {}, {}  // raw sysex bytes, see Sysex in README.md\n",
+            UNKNOWN,
+            bytes(pattern.unknown1),
+            bytes(pattern.unknown2)
+        ));
+    }
     pattern_str.push('\n');
     pattern_str.push_str(&num);
     pattern_str.push_str(&note);
@@ -132,84 +474,429 @@ pub fn pattern_to_string(pattern: &Pattern) -> String {
 
 pub fn next_nonempty_line(lines: &mut Lines) -> String {
     let line = "";
-    while let Some(line) = lines.next() {
+    for line in lines.by_ref() {
         let line = match line.find("//") {
             Some(x) => line[0..x].trim(),
             None => line.trim(),
         };
-        if line.len() > 0 {
+        if !line.is_empty() {
             return line.to_string();
         }
     }
     line.to_owned()
 }
 
-// Originally this funciton was intended to return Vec<&str> and without to_owned but it was causing
-// cannot return value referencing local variable rust? is there a way how to keep line borrowed
-fn split_entries<'a>(lines: &mut Lines, start: &str) -> Result<Vec<String>, Box<dyn Error>> {
-    let line = next_nonempty_line(lines);
-    if !line.starts_with(start) {
-        return Err(format!("Expecting {}: ..., read: {}", start, line).into());
+// pattern lines, matched by key before : in any case, in any order
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Field {
+    ActiveSteps,
+    Triplet,
+    Note,
+    Transpose,
+    Accent,
+    Slide,
+    Time,
+    Unknown,
+}
+
+// accepted spellings, first one is written
+pub const FIELDS: &[(Field, &[&str])] = &[
+    (Field::ActiveSteps, &[ACTIVE_STEPS, "Steps"]),
+    (Field::Triplet, &[TRIPLET, "Triplet mode", "Triplet", "Triplets"]),
+    (Field::Note, &["Note"]),
+    (Field::Transpose, &["Transpose"]),
+    (Field::Accent, &["Accent"]),
+    (Field::Slide, &["Slide"]),
+    (Field::Unknown, &[UNKNOWN]),
+    (Field::Time, &["Tie/Rest", "Time"]),
+];
+
+pub fn find_field(key: &str) -> Option<Field> {
+    let key = key.trim().to_lowercase();
+    FIELDS.iter().find(|(_, names)| names.iter().any(|n| n.to_lowercase() == key)).map(|(field, _)| *field)
+}
+
+fn field_name(field: Field) -> &'static str {
+    FIELDS.iter().find(|(f, _)| *f == field).map_or("", |(_, names)| names[0])
+}
+
+// semitones of C D E F G A B, German H is B
+const LETTERS: &[(char, u8)] = &[('c', 0), ('d', 2), ('e', 4), ('f', 5), ('g', 7), ('a', 9), ('b', 11), ('h', 11)];
+// longer names first, so sol isn't read as so + l
+const SOLFEGE: &[(&str, u8)] =
+    &[("sol", 7), ("do", 0), ("re", 2), ("mi", 4), ("fa", 5), ("so", 7), ("la", 9), ("si", 11), ("ti", 11)];
+// sharps and flats, German cis/des and es/as
+const ACCIDENTALS: &[(&str, i8)] = &[("#", 1), ("♯", 1), ("is", 1), ("b", -1), ("♭", -1), ("es", -1), ("s", -1)];
+
+// C, C#, Db, c, Cis, Des, Do#, Reb, C^, C2 .. C5, empty is C
+// returns note 0-12 and transpose when note has octave
+pub fn parse_note(input: &str) -> Result<(u8, Option<step::Transpose>), Box<dyn Error>> {
+    let wrong = || -> Box<dyn Error> { format!("Unknown note {}", input).into() };
+    let (low, high) = (LOWEST_OCTAVE as i32, HIGHEST_OCTAVE as i32);
+    let out_of_range = || -> Box<dyn Error> { format!("Note {} is out of range C{} - C{}", input, low, high).into() };
+    let lower = input.trim().to_lowercase();
+    if lower.is_empty() {
+        return Ok((0, None));
+    }
+    if lower == "c^" {
+        return Ok((12, None));
+    }
+    let digits = lower.trim_end_matches(|c: char| c.is_ascii_digit());
+    let octave = match &lower[digits.len()..] {
+        "" => None,
+        octave => Some(octave.parse::<i32>().map_err(|_| wrong())?),
+    };
+    let (mut semitone, mut rest) = match SOLFEGE.iter().find(|(name, _)| digits.starts_with(name)) {
+        Some((name, semitone)) => (*semitone as i32, &digits[name.len()..]),
+        None => {
+            let letter = digits.chars().next().ok_or_else(wrong)?;
+            match LETTERS.iter().find(|(l, _)| *l == letter) {
+                Some((_, semitone)) => (*semitone as i32, &digits[letter.len_utf8()..]),
+                None => return Err(wrong()),
+            }
+        }
+    };
+    while !rest.is_empty() {
+        let (name, shift) = ACCIDENTALS.iter().find(|(name, _)| rest.starts_with(name)).ok_or_else(wrong)?;
+        semitone += *shift as i32;
+        rest = &rest[name.len()..];
+    }
+    let octave = match octave {
+        // B# is C and Cb is B without going to another octave, there is nowhere to go
+        None => return Ok((semitone.rem_euclid(12) as u8, None)),
+        // checked before arithmetic, long octave or many sharps shouldn't overflow
+        Some(octave) if (low..=high).contains(&octave) => octave,
+        Some(_) => return Err(out_of_range()),
+    };
+    // B#4 is C5, Cb2 is out of range
+    let midi_note = u8::try_from((octave + 1) * 12 + semitone).map_err(|_| out_of_range())?;
+    let (note, transpose) = Pitch::from_midi_note(midi_note).map_err(|_| out_of_range())?.note();
+    Ok((note, Some(transpose)))
+}
+
+// value of a pattern line with its 1-based column, for diagnostics
+struct Cell<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+impl Cell<'_> {
+    fn width(&self) -> usize {
+        self.text.chars().count()
+    }
+}
+
+// "a, b ,c" starting at column -> trimmed cells with their columns
+fn split_entries(values: &str, column: usize) -> Vec<Cell<'_>> {
+    let mut cells = Vec::new();
+    let mut column = column;
+    for part in values.split(',') {
+        let leading = part.chars().take_while(|c| c.is_whitespace()).count();
+        cells.push(Cell { text: part.trim(), column: column + leading });
+        column += part.chars().count() + 1;
+    }
+    cells
+}
+
+// line of pattern file: number, line as is, part before comment
+struct SourceLine<'a> {
+    number: usize,
+    source: &'a str,
+    code: &'a str,
+}
+
+impl SourceLine<'_> {
+    fn error(&self, diagnostics: &mut Diagnostics, cell: &Cell, message: String) {
+        diagnostics.push(self.number, cell.column, cell.width(), message, self.source);
+    }
+
+    // whole line without leading whitespace
+    fn cell(&self) -> Cell<'_> {
+        let leading = self.code.chars().take_while(|c| c.is_whitespace()).count();
+        Cell { text: self.code.trim(), column: leading + 1 }
+    }
+}
+
+// tokens which could be written in step lines, for suggestions
+fn field_tokens(field: Field) -> &'static [&'static str] {
+    match field {
+        Field::Note => NOTE,
+        Field::Transpose => &[step::UP, step::DN],
+        Field::Accent => &[step::AC],
+        Field::Slide => &[step::SL],
+        Field::Time => &[step::TI, step::RE, step::TR],
+        Field::ActiveSteps | Field::Triplet | Field::Unknown => &[],
+    }
+}
+
+// Active Steps: 16, Triplet Time: Off, either could be on its own line
+fn parse_steps_line(
+    line: &SourceLine,
+    diagnostics: &mut Diagnostics,
+    active_steps: &mut Option<u8>,
+    triplet: &mut Option<bool>,
+) {
+    for cell in split_entries(line.code, 1) {
+        let (key, value) = match cell.text.split_once(':') {
+            Some((key, value)) => (key, value.trim()),
+            None => {
+                let message = format!("Expecting {}: # or {}: On|Off, read: {}", ACTIVE_STEPS, TRIPLET, cell.text);
+                line.error(diagnostics, &cell, message);
+                continue;
+            }
+        };
+        // value is underlined, not the whole key: value
+        let value_cell = Cell { text: value, column: cell.column + cell.text.chars().count() - value.chars().count() };
+        match find_field(key) {
+            Some(Field::ActiveSteps) => match value.parse::<u8>() {
+                Ok(x @ 1..=16) => *active_steps = Some(x),
+                _ => {
+                    let message = format!("{} should be from 1 to 16, read: {}", ACTIVE_STEPS, value);
+                    line.error(diagnostics, &value_cell, message);
+                }
+            },
+            Some(Field::Triplet) => match value.to_lowercase().as_str() {
+                "on" => *triplet = Some(true),
+                "off" => *triplet = Some(false),
+                _ => {
+                    let message = format!(
+                        "{} should be On or Off, read: {}{}",
+                        TRIPLET,
+                        value,
+                        did_you_mean(value, &["On", "Off"])
+                    );
+                    line.error(diagnostics, &value_cell, message);
+                }
+            },
+            _ => {
+                let message = format!("Expecting {}: # or {}: On|Off, read: {}", ACTIVE_STEPS, TRIPLET, cell.text);
+                line.error(diagnostics, &cell, message);
+            }
+        }
     }
-    let vals: Vec<String> =
-        (&line[start.len()..]).split(",").collect::<Vec<&str>>().iter().map(|x| x.trim().to_owned()).collect();
+}
 
-    if vals.len() != 16 {
-        return Err(format!("Line with '{}' should have 16 values, {} found instead", start.trim(), vals.len()).into());
+// Unknown: 00 01, 00 00, two hex bytes for each of unknown fields
+fn parse_unknown_line(
+    line: &SourceLine,
+    values: &str,
+    column: usize,
+    diagnostics: &mut Diagnostics,
+) -> Option<([u8; 2], [u8; 2])> {
+    let cells = split_entries(values, column);
+    let mut unknown = Vec::new();
+    for cell in cells.iter() {
+        let bytes = cell.text.split_whitespace().map(|b| u8::from_str_radix(b, 16)).collect::<Vec<_>>();
+        match bytes.as_slice() {
+            // sysex data bytes are 7 bit
+            [Ok(a @ 0..=0x7f), Ok(b @ 0..=0x7f)] => unknown.push([*a, *b]),
+            _ => {
+                let message = format!("Expecting two hex bytes from 00 to 7f like 00 01, read: {}", cell.text);
+                line.error(diagnostics, cell, message);
+                return None;
+            }
+        }
+    }
+    match unknown.as_slice() {
+        [unknown1, unknown2] => Some((*unknown1, *unknown2)),
+        _ => {
+            let message = format!("Expecting {}: 00 01, 00 00, read: {}", UNKNOWN, values.trim());
+            line.error(diagnostics, &line.cell(), message);
+            None
+        }
     }
-    Ok(vals)
 }
 
 pub fn string_to_pattern(string_pattern: String) -> Result<Pattern, Box<dyn Error>> {
+    Ok(string_to_pattern_with_metadata(string_pattern)?.0)
+}
+
+// reads both v1 and v2, metadata is empty for v1
+pub fn string_to_pattern_with_metadata(string_pattern: String) -> Result<(Pattern, Metadata), Box<dyn Error>> {
+    Ok(parse_pattern(&string_pattern)?)
+}
+
+// same, errors are prefixed with file name
+pub fn file_to_pattern_with_metadata(
+    filename: &str,
+    string_pattern: String,
+) -> Result<(Pattern, Metadata), Box<dyn Error>> {
+    match parse_pattern(&string_pattern) {
+        Ok(result) => Ok(result),
+        Err(diagnostics) => Err(Box::new(Diagnostics { file: Some(filename.to_owned()), ..diagnostics })),
+    }
+}
+
+// lines could go in any order and have less than 16 entries, missing steps are C without anything
+// every problem is reported, not only the first one
+pub(crate) fn parse_pattern(string_pattern: &str) -> Result<(Pattern, Metadata), Diagnostics> {
     let mut pattern: Pattern = Default::default();
-    let mut lines = string_pattern.lines();
-    // TD-3 Pattern
-    let line = next_nonempty_line(&mut lines);
-    if line != TD3_PATTERN {
-        return Err(format!("Expecting {}, read: {}", TD3_PATTERN, line).into());
-    }
-    // Active Steps
-    let line = next_nonempty_line(&mut lines);
-    match scan_fmt!(&line, "{[^:]}: {d}, {[^:]}: {}", String, u8, String, String) {
-        Err(_) => return Err(format!("Expecting {}: # and {}: #, read: {}", ACTIVE_STEPS, TRIPLET, line).into()),
-        Ok((a_s, a_s_value, trip, trip_value)) => {
-            if a_s != ACTIVE_STEPS || trip != TRIPLET {
-                return Err(format!("Expecting {}: # and {}: #, read: {}", ACTIVE_STEPS, TRIPLET, line).into());
+    let mut metadata: Metadata = Default::default();
+    let mut diagnostics: Diagnostics = Default::default();
+    let mut lines = string_pattern
+        .lines()
+        .enumerate()
+        .map(|(n, line)| SourceLine {
+            number: n + 1,
+            source: line,
+            code: line.find("//").map_or(line, |x| &line[0..x]),
+        })
+        .filter(|line| !line.code.trim().is_empty())
+        .peekable();
+    // TD-3 Pattern, line which isn't header is read as the rest of the pattern
+    let mut v2 = false;
+    match lines.peek() {
+        Some(line) if line.code.trim().eq_ignore_ascii_case(TD3_PATTERN) => {
+            lines.next();
+        }
+        Some(line) if line.code.trim().eq_ignore_ascii_case(TD3_PATTERN_V2) => {
+            v2 = true;
+            lines.next();
+        }
+        Some(line) => {
+            let message = format!("Expecting {} or {}, read: {}", TD3_PATTERN, TD3_PATTERN_V2, line.code.trim());
+            line.error(&mut diagnostics, &line.cell(), message);
+            // misspelled header, pattern line without header is read as such
+            if !line.code.contains(':') {
+                lines.next();
             }
-            pattern.active_steps = a_s_value;
-            pattern.triplet = trip_value == "On";
         }
+        None => {
+            diagnostics.push_file(format!("Expecting {}, file is empty", TD3_PATTERN));
+            return Err(diagnostics);
+        }
+    }
+    let mut active_steps = None;
+    let mut triplet = None;
+    let mut unknown = None;
+    let mut rows: Vec<(Field, SourceLine, Vec<Cell>)> = Vec::new();
+    let mut keys = FIELDS.iter().map(|(_, names)| names[0]).collect::<Vec<&str>>();
+    if v2 {
+        keys.extend_from_slice(METADATA_FIELDS);
     }
-    let note = split_entries(&mut lines, NOTE_S)?;
-    let transpose = split_entries(&mut lines, TRANSPOSE_S)?;
-    let accent = split_entries(&mut lines, ACCENT_S)?;
-    let slide = split_entries(&mut lines, SLIDE_S)?;
-    let time = split_entries(&mut lines, TIME)?;
+    for line in lines {
+        let (key, values) = match line.code.split_once(':') {
+            Some(kv) => kv,
+            None => {
+                line.error(
+                    &mut diagnostics,
+                    &line.cell(),
+                    format!("Expecting Name: values, read: {}", line.code.trim()),
+                );
+                continue;
+            }
+        };
+        let key_cell = Cell { text: key.trim(), column: line.cell().column };
+        match find_field(key) {
+            Some(Field::ActiveSteps | Field::Triplet) => {
+                parse_steps_line(&line, &mut diagnostics, &mut active_steps, &mut triplet)
+            }
+            Some(Field::Unknown) if unknown.is_some() => {
+                line.error(&mut diagnostics, &key_cell, format!("Line '{}' is given twice", UNKNOWN))
+            }
+            Some(Field::Unknown) => {
+                unknown = parse_unknown_line(&line, values, key.chars().count() + 2, &mut diagnostics);
+            }
+            Some(field) if rows.iter().any(|(f, _, _)| *f == field) => {
+                line.error(&mut diagnostics, &key_cell, format!("Line '{}' is given twice", field_name(field)))
+            }
+            Some(field) => {
+                let cells = split_entries(values, key.chars().count() + 2);
+                if let Some(extra) = cells.get(16) {
+                    let message =
+                        format!("Line with '{}' should have up to 16 values, {} found", field_name(field), cells.len());
+                    line.error(&mut diagnostics, extra, message);
+                }
+                rows.push((field, line, cells));
+            }
+            None if v2 => match parse_metadata_line(&mut metadata, line.code) {
+                Ok(true) => (),
+                Ok(false) => {
+                    let message = format!("Unknown line {}: ...{}", key.trim(), did_you_mean(key, &keys));
+                    line.error(&mut diagnostics, &key_cell, message);
+                }
+                Err(e) => {
+                    let value = values.trim();
+                    let column = line.code.chars().count() - values.trim_start().chars().count() + 1;
+                    line.error(&mut diagnostics, &Cell { text: value, column }, e.to_string());
+                }
+            },
+            None => {
+                let message = format!("Unknown line {}: ...{}", key.trim(), did_you_mean(key, &keys));
+                line.error(&mut diagnostics, &key_cell, message);
+            }
+        }
+    }
+    let row = |field: Field| rows.iter().find(|(f, _, _)| *f == field);
+    if row(Field::Note).is_none() {
+        diagnostics.push_file(format!("Expecting {}: ... line", field_name(Field::Note)));
+    }
+    // without Active Steps the longest line tells how many steps there are
+    pattern.active_steps =
+        active_steps.unwrap_or_else(|| rows.iter().map(|(_, _, cells)| cells.len().min(16)).max().unwrap_or(16) as u8);
+    pattern.triplet = triplet.unwrap_or(false);
+    if let Some((unknown1, unknown2)) = unknown {
+        pattern.unknown1 = unknown1;
+        pattern.unknown2 = unknown2;
+    }
+    // reports problem with a cell, missing cells are always valid
+    let check =
+        |diagnostics: &mut Diagnostics, field: Field, i: usize, message: &dyn Fn(&str) -> String| match row(field) {
+            Some((_, line, cells)) if i < cells.len() => line.error(diagnostics, &cells[i], message(cells[i].text)),
+            _ => diagnostics.push_file(message("")),
+        };
+    let entry = |field: Field, i: usize| row(field).and_then(|(_, _, cells)| cells.get(i)).map_or("", |c| c.text);
     for i in 0..=15 {
         let s = &mut pattern.step[i];
-        // todo: turn to macro or fn and callbacks
-        match NOTE.iter().position(|&n| note[i] == n) {
-            Some(x) => s.note = x as u8,
-            None => return Err(format!("Wrong '{}' on postion {}: {}", NOTE_S.trim(), i, note[i]).into()),
+        let wrong = |field: Field| {
+            move |text: &str| {
+                let suggestion = did_you_mean(text, field_tokens(field));
+                format!("Wrong '{}' on step {}: {}{}", field_name(field), i + 1, text, suggestion)
+            }
         };
-        match step::Transpose::from_str(&transpose[i]) {
-            Ok(x) => s.transpose = x,
-            _ => return Err(format!("Wrong '{}' on postion {}: {}", TRANSPOSE_S.trim(), i, transpose[i]).into()),
+        let (note, octave) = match parse_note(entry(Field::Note, i)) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                let suggestion = did_you_mean(entry(Field::Note, i), NOTE);
+                check(&mut diagnostics, Field::Note, i, &|_| format!("{} on step {}{}", e, i + 1, suggestion));
+                (0, None)
+            }
         };
-        match step::Accent::from_str(&accent[i]) {
-            Ok(x) => s.accent = x,
-            _ => return Err(format!("Wrong '{}' on postion {}: {}", ACCENT_S.trim(), i, accent[i]).into()),
+        let transpose = match (step::Transpose::from_str(entry(Field::Transpose, i)), octave) {
+            (Err(_), _) => {
+                check(&mut diagnostics, Field::Transpose, i, &wrong(Field::Transpose));
+                step::Transpose::Normal
+            }
+            (Ok(step::Transpose::Normal), Some(x)) => x,
+            (Ok(x), Some(y)) if x != y => {
+                check(&mut diagnostics, Field::Note, i, &|text| {
+                    format!("Note {} on step {} has octave which conflicts with transpose", text, i + 1)
+                });
+                x
+            }
+            (Ok(x), _) => x,
         };
-        match step::Slide::from_str(&slide[i]) {
+        // parse_note gives 0-12 only
+        s.set_note(note, transpose).unwrap();
+        match step::Accent::from_str(entry(Field::Accent, i)) {
+            Ok(x) => s.accent = x,
+            Err(_) => check(&mut diagnostics, Field::Accent, i, &wrong(Field::Accent)),
+        }
+        match step::Slide::from_str(entry(Field::Slide, i)) {
             Ok(x) => s.slide = x,
-            _ => return Err(format!("Wrong '{}' on postion {}: {}", SLIDE_S.trim(), i, slide[i]).into()),
-        };
-        match step::Time::from_str(&time[i]) {
+            Err(_) => check(&mut diagnostics, Field::Slide, i, &wrong(Field::Slide)),
+        }
+        match step::Time::from_str(entry(Field::Time, i)) {
             Ok(x) => s.time = x,
-            _ => return Err(format!("Wrong '{}' on postion {}: {}", TIME.trim(), i, time[i]).into()),
-        };
+            Err(_) => check(&mut diagnostics, Field::Time, i, &wrong(Field::Time)),
+        }
+    }
+    // in file order, problems with the file as a whole at the end
+    diagnostics.diagnostics.sort_by_key(|d| (d.line == 0, d.line, d.column));
+    match diagnostics.is_empty() {
+        true => Ok((pattern, metadata)),
+        false => Err(diagnostics),
     }
-    Ok(pattern)
 }
 
 macro_rules! u16_to_four_u8 {
@@ -224,7 +911,7 @@ macro_rules! u16_to_four_u8 {
 }
 
 // output size should be 115 bytes
-pub fn pattern_to_sysex(pattern: &Pattern, group: u8, pnum: u8, ab: u8) -> Vec<u8> {
+pub fn pattern_to_sysex(pattern: &Pattern, slot: SlotAddress) -> Vec<u8> {
     let mut note: [u8; 32] = [0; 32];
     let mut accent: [u8; 32] = [0; 32];
     let mut slide: [u8; 32] = [0; 32];
@@ -233,30 +920,343 @@ pub fn pattern_to_sysex(pattern: &Pattern, group: u8, pnum: u8, ab: u8) -> Vec<u
     for i in 0..=15 {
         let d = i << 1;
         let s = &pattern.step[i];
-        let hbit = if s.note >= 12 { 0x80 } else { 0 };
-        let composed_note: u8 = 12 + s.note + ((s.transpose as u8) * 12) + hbit;
+        // device note index, see decode_pattern
+        let hbit = if s.note() >= 12 { 0x80 } else { 0 };
+        let composed_note: u8 = 12 + s.note() + ((s.transpose() as u8) * 12) + hbit;
         note[d] = (composed_note & 0b11110000) >> 4;
         note[d + 1] = composed_note & 0b00001111;
         accent[d + 1] = s.accent as u8;
         slide[d + 1] = s.slide as u8;
-        tie = tie + (((s.time as u16) & 0b01) << i);
-        rest = rest + ((((s.time as u16) & 0b10) >> 1) << i);
+        tie += ((s.time as u16) & 0b01) << i;
+        rest += (((s.time as u16) & 0b10) >> 1) << i;
         if cfg!(debug_assertions) {
-            println!("{:02}: t {} r {}", i, ((s.time as u16) & 0b01), (((s.time as u16) & 0b10) >> 1));
+            eprintln!("{:02}: t {} r {}", i, ((s.time as u16) & 0b01), (((s.time as u16) & 0b10) >> 1));
         }
     }
     // create sysex
     let mut sysex: Vec<u8> = Vec::new();
     sysex.push(0x78);
-    sysex.extend_from_slice(&[group, pnum + (ab << 3)]);
-    sysex.extend_from_slice(&[00, 01]);
+    sysex.extend_from_slice(&slot.to_sysex());
+    sysex.extend_from_slice(&pattern.unknown1);
     sysex.extend_from_slice(&note);
     sysex.extend_from_slice(&accent);
     sysex.extend_from_slice(&slide);
-    sysex.extend_from_slice(&[00, pattern.triplet as u8]);
+    sysex.extend_from_slice(&[0x00, pattern.triplet as u8]);
     sysex.extend_from_slice(&[(pattern.active_steps & 0xF0) >> 4, pattern.active_steps & 0x0F]);
-    sysex.extend_from_slice(&[00, 00]);
+    sysex.extend_from_slice(&pattern.unknown2);
     sysex.extend_from_slice(&(u16_to_four_u8!(tie)));
     sysex.extend_from_slice(&(u16_to_four_u8!(rest)));
-    return sysex;
+    sysex
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::slot::Side;
+    use proptest::prelude::*;
+    use step::{Accent, Slide, Step, Time, Transpose};
+
+    fn step_strategy() -> impl Strategy<Value = Step> {
+        (0..=12u8, 0..=2u8, any::<bool>(), any::<bool>(), 0..=3u8).prop_map(|(note, transpose, accent, slide, time)| {
+            let mut step = Step::from_note(note, Transpose::try_from(transpose).unwrap()).unwrap();
+            step.accent = if accent { Accent::On } else { Accent::Off };
+            step.slide = if slide { Slide::On } else { Slide::Off };
+            step.time = Time::try_from(time).unwrap();
+            step
+        })
+    }
+
+    pub(crate) fn pattern_strategy() -> impl Strategy<Value = Pattern> {
+        let unknown = prop::array::uniform2(0..0x80u8);
+        (any::<bool>(), 1..=16u8, prop::array::uniform16(step_strategy()), unknown.clone(), unknown).prop_map(
+            |(triplet, active_steps, steps, unknown1, unknown2)| {
+                let builder = Pattern::builder().triplet(triplet).active_steps(active_steps).steps(steps);
+                builder.unknown(unknown1, unknown2).build().unwrap()
+            },
+        )
+    }
+
+    pub(crate) fn slot_strategy() -> impl Strategy<Value = SlotAddress> {
+        (0..4u8, 0..8u8, any::<bool>())
+            .prop_map(|(group, number, b)| SlotAddress::new(group, number, if b { Side::B } else { Side::A }).unwrap())
+    }
+
+    // values are trimmed when read, so they neither start nor end with space
+    fn metadata_strategy() -> impl Strategy<Value = Metadata> {
+        let text = "[A-Za-z0-9#]([A-Za-z0-9#' /\\\\]{0,14}[A-Za-z0-9#'/])?";
+        (
+            prop::option::of(text),
+            prop::option::of(text),
+            prop::option::of(20..=300u16),
+            prop::collection::vec("[a-z]{1,8}", 0..4),
+            prop::option::of("[A-G][#b]?( major| minor)?"),
+            prop::option::of(slot_strategy()),
+            prop::option::of((2000..2100u16, 1..=12u8, 1..=28u8)),
+            prop::collection::vec(text, 0..3),
+        )
+            .prop_map(|(name, author, tempo, tags, key, source, created, notes)| Metadata {
+                name,
+                author,
+                tempo: tempo.map(f32::from),
+                tags,
+                key,
+                source,
+                created: created.map(|(y, m, d)| format!("{:04}-{:02}-{:02}", y, m, d)),
+                notes,
+            })
+    }
+
+    fn sysex(pattern: &Pattern) -> Vec<u8> {
+        pattern_to_sysex(pattern, Default::default())
+    }
+
+    fn single_step_pattern(step: Step, active_steps: u8) -> Pattern {
+        Pattern::from_steps([step; 16], active_steps, false).unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn text_round_trip(pattern in pattern_strategy()) {
+            let text = pattern_to_string(&pattern);
+            let read = string_to_pattern(text.clone()).unwrap();
+            prop_assert_eq!(&read, &pattern);
+            prop_assert_eq!(read.to_string(), text);
+        }
+
+        #[test]
+        fn text_with_metadata_round_trip(pattern in pattern_strategy(), metadata in metadata_strategy()) {
+            let text = pattern_to_string_with_metadata(&pattern, &metadata);
+            let (read, read_metadata) = string_to_pattern_with_metadata(text).unwrap();
+            prop_assert_eq!(read, pattern);
+            prop_assert_eq!(read_metadata, metadata);
+        }
+
+        #[test]
+        fn sysex_round_trip(pattern in pattern_strategy(), slot in slot_strategy()) {
+            let msg = pattern_to_sysex(&pattern, slot);
+            prop_assert_eq!(msg.len(), PATTERN_SYSEX_LEN);
+            prop_assert_eq!(SlotAddress::from_sysex(msg[1], msg[2]).unwrap(), slot);
+            prop_assert_eq!(decode_pattern(&msg, Some(slot), Decode::Strict).unwrap(), pattern);
+        }
+
+        #[test]
+        fn upper_c_as_note_index(pattern in pattern_strategy()) {
+            // upper C transposed up could come as note index 0x30 without 0x80 flag
+            let mut msg = sysex(&pattern);
+            for (n, s) in pattern.steps().iter().enumerate() {
+                if s.note() == 12 && s.transpose() == Transpose::Up {
+                    msg[0x05 + n * 2] = 0x03;
+                    msg[0x06 + n * 2] = 0x00;
+                }
+            }
+            prop_assert_eq!(sysex_to_pattern(&msg).unwrap(), pattern);
+        }
+    }
+
+    #[test]
+    fn every_step_combination() {
+        for note in 0..=12u8 {
+            for transpose in [Transpose::Down, Transpose::Normal, Transpose::Up] {
+                for time in [Time::Tie, Time::Normal, Time::TieRest, Time::Rest] {
+                    for (accent, slide) in [(Accent::Off, Slide::Off), (Accent::On, Slide::On)] {
+                        let mut step = Step::from_note(note, transpose).unwrap();
+                        (step.accent, step.slide, step.time) = (accent, slide, time);
+                        let pattern = single_step_pattern(step, 16);
+                        assert_eq!(string_to_pattern(pattern_to_string(&pattern)).unwrap(), pattern);
+                        assert_eq!(sysex_to_pattern(&sysex(&pattern)).unwrap(), pattern);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_active_steps_value() {
+        for active_steps in 1..=16u8 {
+            for triplet in [false, true] {
+                let pattern = Pattern::builder().triplet(triplet).active_steps(active_steps).build().unwrap();
+                assert_eq!(string_to_pattern(pattern_to_string(&pattern)).unwrap(), pattern);
+                assert_eq!(sysex_to_pattern(&sysex(&pattern)).unwrap(), pattern);
+            }
+        }
+    }
+
+    #[test]
+    fn upper_c_flag_and_note_index_are_the_same() {
+        let step = Step::from_note(12, Transpose::Up).unwrap();
+        let msg = sysex(&single_step_pattern(step, 1));
+        // 12 + C^ + UP with 0x80 flag
+        assert_eq!(msg[0x05..0x07], [0x0B, 0x00]);
+        let mut index = msg.clone();
+        index[0x05] = 0x03;
+        assert_eq!(sysex_to_pattern(&index).unwrap(), single_step_pattern(step, 1));
+    }
+
+    #[test]
+    fn note_index_is_24_below_midi_note() {
+        for note in 0..=12u8 {
+            for transpose in [Transpose::Down, Transpose::Normal, Transpose::Up] {
+                let step = Step::from_note(note, transpose).unwrap();
+                let msg = sysex(&single_step_pattern(step, 1));
+                let index = ((msg[0x05] & 0x07) << 4) + msg[0x06];
+                assert_eq!(index + 24, step.pitch().midi_note(), "{:?}", step);
+            }
+        }
+    }
+
+    #[test]
+    fn transpose_is_written_as_it_is_read() {
+        let written = |transpose: Transpose| format!("{:?}", transpose);
+        assert_eq!(written(Transpose::Down).trim(), step::DN);
+        assert_eq!(written(Transpose::Up).trim(), step::UP);
+        for transpose in [Transpose::Down, Transpose::Normal, Transpose::Up] {
+            assert!(Transpose::from_str(&written(transpose)) == Ok(transpose));
+        }
+    }
+
+    // inputs found by fuzzing which used to panic
+    #[test]
+    fn fuzzed_inputs_are_errors() {
+        assert!(parse_note("D13").is_err());
+        assert!(parse_note(&format!("c{}3", "#".repeat(200))).is_err());
+        let mut msg = sysex(&Default::default());
+        // upper C flag on DN C
+        msg[0x05] = 0x08;
+        msg[0x06] = 0x0C;
+        assert!(sysex_to_pattern(&msg).is_err());
+        assert!(decode_pattern(&msg, None, Decode::Lenient).is_ok());
+        msg[0x67] = 0x0F;
+        assert_eq!(decode_pattern(&msg, None, Decode::Lenient).unwrap().active_steps(), 16);
+    }
+
+    // unknown bytes are kept, but only as data bytes, pattern with 0x80 couldn't be sent back
+    #[test]
+    fn unknown_bytes_above_7f_are_errors() {
+        for offset in [0x03, 0x04, 0x69, 0x6A] {
+            let mut msg = sysex(&Default::default());
+            msg[offset] = 0x85;
+            match sysex_to_pattern(&msg) {
+                Err(SysexError::Value { offset: at, value: 0x85, field: "unknown" }) => assert_eq!(at, offset),
+                other => panic!("{:#04x}: {:?}", offset, other),
+            }
+            let pattern = decode_pattern(&msg, None, Decode::Lenient).unwrap();
+            let (unknown1, unknown2) = pattern.unknown();
+            assert!(unknown1.iter().chain(unknown2.iter()).all(|b| *b <= 0x7F), "{:?}", pattern.unknown());
+            msg[offset] = 0x7F;
+            assert!(sysex_to_pattern(&msg).is_ok());
+        }
+    }
+
+    // --show-unknown text keeps unusual bytes, so they survive download and upload through text file
+    #[test]
+    fn unknown_bytes_round_trip_through_text() {
+        let pattern = Pattern::builder().unknown([0x12, 0x7F], [0x05, 0x00]).build().unwrap();
+        for show_unknown in [false, true] {
+            let text = pattern_to_string_with_unknown(&pattern, &Default::default(), show_unknown);
+            assert!(text.contains("Unknown: 12 7f, 05 00"), "{}", text);
+            assert_eq!(string_to_pattern(text).unwrap(), pattern);
+        }
+        let usual = pattern_to_string_with_unknown(&Pattern::new(), &Default::default(), true);
+        assert!(usual.contains("Unknown: 00 01, 00 00"), "{}", usual);
+        assert_eq!(string_to_pattern(usual).unwrap().unknown(), ([0x00, 0x01], [0x00, 0x00]));
+    }
+
+    #[test]
+    fn lenient_spellings() {
+        let (dn, up) = (Some(Transpose::Down), Some(Transpose::Up));
+        let notes: &[(&str, (u8, Option<Transpose>))] = &[
+            ("Db", (1, None)),
+            ("eb", (3, None)),
+            ("C2", (0, dn)),
+            ("fis", (6, None)),
+            ("Es", (3, None)),
+            ("As", (8, None)),
+            ("Ces", (11, None)),
+            ("H", (11, None)),
+            ("do", (0, None)),
+            ("sol#", (8, None)),
+            ("C^", (12, None)),
+            ("B#4", (12, up)),
+            ("", (0, None)),
+        ];
+        for (input, expected) in notes {
+            assert_eq!(parse_note(input).unwrap(), *expected, "{}", input);
+        }
+        for wrong in ["X", "Cx", "C1", "C6", "Cb2", "do re"] {
+            assert!(parse_note(wrong).is_err(), "{}", wrong);
+        }
+        // fields in any order, other names and case of keys
+        let canonical = "TD-3 Pattern
+Active Steps: 4, Triplet Time: On
+Note: C#, D#, B, C^
+Transpose: DN, , UP,
+Accent: AC, , , AC
+Slide: , SL, ,
+Tie/Rest: , TI, RE,
+";
+        let lenient = "TD-3 Pattern
+time: , TI, RE
+Triplet mode: On
+slide: , SL
+NOTE: Db2, eb, H4, C^
+steps: 4
+accent: AC, , , AC
+";
+        let expected = string_to_pattern(canonical.to_owned()).unwrap();
+        assert_eq!(string_to_pattern(lenient.to_owned()).unwrap(), expected);
+        assert_eq!(pattern_to_string(&string_to_pattern(lenient.to_owned()).unwrap()), pattern_to_string(&expected));
+    }
+
+    // every pitch set through mutable steps is written and read back
+    #[test]
+    fn mutable_steps_are_always_written() {
+        let mut pattern = Pattern::builder().active_steps(16).build().unwrap();
+        for octave in LOWEST_OCTAVE..=HIGHEST_OCTAVE {
+            for class in 0..12 {
+                let pitch = match Pitch::new(class, octave) {
+                    Ok(pitch) => pitch,
+                    Err(_) => continue,
+                };
+                for step in pattern.iter_mut() {
+                    step.set_pitch(pitch);
+                }
+                pattern[15].set_note(12, Transpose::Down).unwrap();
+                assert!(pattern[0].set_note(13, Transpose::Normal).is_err());
+                assert_eq!(string_to_pattern(pattern_to_string(&pattern)).unwrap(), pattern);
+                assert_eq!(sysex_to_pattern(&sysex(&pattern)).unwrap(), pattern);
+            }
+        }
+    }
+
+    #[test]
+    fn pattern_api() {
+        let mut accented = Step::default();
+        accented.accent = Accent::On;
+        let mut pattern = Pattern::builder().step(accented).step(Default::default()).triplet(true).build().unwrap();
+        assert_eq!((pattern.active_steps(), pattern.triplet()), (2, true));
+        assert_eq!(pattern[0], accented);
+        assert_eq!(pattern.iter().count(), 2);
+        pattern[1].slide = Slide::On;
+        assert_eq!(pattern.step(1).map(|s| s.slide), Some(Slide::On));
+        for step in &mut pattern {
+            step.time = Time::Rest;
+        }
+        assert!(pattern.steps()[0..2].iter().all(|s| s.time == Time::Rest) && pattern[2].time == Time::Normal);
+        assert_eq!(pattern.to_string(), pattern_to_string(&pattern));
+        let mut copy = pattern.clone();
+        assert_eq!(copy, pattern);
+        copy.set_triplet(false);
+        assert_ne!(copy, pattern);
+        // nothing out of range gets in
+        assert!(pattern.set_active_steps(0).is_err() && pattern.set_active_steps(17).is_err());
+        assert!(pattern.set_step(16, accented).is_err());
+        // notes are checked when step is made, so mutable steps can't get out of range either
+        assert!(Step::from_note(13, Transpose::Normal).is_err() && pattern[0].set_note(13, Transpose::Up).is_err());
+        assert_eq!(pattern[0].note(), 0);
+        assert!(pattern.set_unknown([0x80, 0], UNKNOWN2).is_err());
+        assert_eq!(pattern.active_steps(), 2);
+        assert!(Pattern::builder().steps([accented; 17]).build().is_err());
+        assert!(Pattern::builder().active_steps(0).build().is_err());
+        assert_eq!(Pattern::new(), Pattern::builder().build().unwrap());
+    }
 }
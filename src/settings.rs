@@ -0,0 +1,223 @@
+use std::error::Error;
+
+#[cfg(feature = "midi")]
+use crate::device::Profile;
+#[cfg(feature = "midi")]
+use crate::midicomm::send_sysex;
+use crate::pattern::next_nonempty_line;
+
+pub struct Setting {
+    pub name: &'static str,
+    // parameter id in get/set sysex
+    #[cfg_attr(not(feature = "midi"), allow(dead_code))]
+    id: u8,
+    // value shown to user is raw value + offset, e.g. midi channels are 0-15 on device and 1-16 for humans
+    offset: u8,
+    // maximum raw value
+    max: u8,
+    // names for raw values 0, 1, ..., empty for numeric settings
+    labels: &'static [&'static str],
+}
+
+// ids and 75/76 messages are not confirmed on real devices yet, see Settings sysex in README.md
+pub const SETTINGS: &[Setting] = &[
+    Setting { name: "midi-in-channel", id: 0x00, offset: 1, max: 15, labels: &[] },
+    Setting { name: "midi-out-channel", id: 0x01, offset: 1, max: 15, labels: &[] },
+    Setting { name: "clock-source", id: 0x02, offset: 0, max: 3, labels: &["internal", "midi", "usb", "trigger"] },
+    Setting { name: "accent-velocity", id: 0x03, offset: 0, max: 127, labels: &[] },
+    Setting { name: "key-priority", id: 0x04, offset: 0, max: 2, labels: &["low", "high", "last"] },
+    Setting { name: "pitch-bend-range", id: 0x05, offset: 0, max: 12, labels: &[] },
+];
+
+#[cfg(feature = "midi")]
+const GET_SETTING: u8 = 0x75;
+#[cfg(feature = "midi")]
+const SET_SETTING: u8 = 0x76;
+
+impl Setting {
+    pub fn parse_value(&self, value: &str) -> Result<u8, Box<dyn Error>> {
+        let value = value.trim().to_lowercase();
+        if let Some(x) = self.labels.iter().position(|&l| l == value) {
+            return Ok(x as u8);
+        }
+        match value.parse::<u8>() {
+            Ok(x) if self.labels.is_empty() && x >= self.offset && x - self.offset <= self.max => Ok(x - self.offset),
+            _ => Err(format!("Wrong value for {}: {}, expected {}", self.name, value, self.range()).into()),
+        }
+    }
+
+    pub fn format_value(&self, raw: u8) -> String {
+        match self.labels.get(raw as usize) {
+            Some(label) => label.to_string(),
+            None => format!("{}", raw as u16 + self.offset as u16),
+        }
+    }
+
+    // human readable list of accepted values
+    pub fn range(&self) -> String {
+        if self.labels.is_empty() {
+            format!("{}-{}", self.offset, self.max as u16 + self.offset as u16)
+        } else {
+            self.labels.join("|")
+        }
+    }
+}
+
+pub fn find_setting(name: &str) -> Result<&'static Setting, Box<dyn Error>> {
+    match SETTINGS.iter().find(|s| s.name == name.trim()) {
+        Some(setting) => Ok(setting),
+        None => {
+            let names = SETTINGS.iter().map(|s| s.name).collect::<Vec<&str>>().join(", ");
+            Err(format!("Unknown setting {}, available settings: {}", name, names).into())
+        }
+    }
+}
+
+// "name=value" -> setting, raw value
+pub fn parse_assignment(assignment: &str) -> Result<(&'static Setting, u8), Box<dyn Error>> {
+    match assignment.split_once('=') {
+        Some((name, value)) => {
+            let setting = find_setting(name)?;
+            Ok((setting, setting.parse_value(value)?))
+        }
+        None => Err(format!("Expecting setting=value, read: {}", assignment).into()),
+    }
+}
+
+#[cfg(feature = "midi")]
+pub fn get_settings(
+    out_conn: &mut midir::MidiOutputConnection,
+    rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    profile: &Profile,
+) -> Result<Vec<(&'static Setting, u8)>, Box<dyn Error>> {
+    let mut values = Vec::new();
+    for setting in SETTINGS.iter() {
+        // response is SET_SETTING, id, value split in nibbles
+        let msg = send_sysex(out_conn, rx, profile, setting.name, &[GET_SETTING, setting.id])?;
+        if msg.len() < 4 || msg[0] != SET_SETTING || msg[1] != setting.id {
+            return Err(format!("Unexpected response for {}: {:02x?}", setting.name, msg).into());
+        }
+        let raw = (msg[2] << 4) + (msg[3] & 0x0F);
+        if raw > setting.max {
+            return Err(format!("Device reported out of range value for {}: {}", setting.name, raw).into());
+        }
+        values.push((setting, raw));
+    }
+    Ok(values)
+}
+
+#[cfg(feature = "midi")]
+pub fn set_setting(
+    out_conn: &mut midir::MidiOutputConnection,
+    rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    profile: &Profile,
+    setting: &Setting,
+    raw: u8,
+) -> Result<(), Box<dyn Error>> {
+    send_sysex(out_conn, rx, profile, setting.name, &[SET_SETTING, setting.id, (raw & 0xF0) >> 4, raw & 0x0F])?;
+    Ok(())
+}
+
+const TD3_SETTINGS: &str = "TD-3 Settings";
+
+pub fn settings_to_string(values: &[(&Setting, u8)]) -> String {
+    let mut settings_str = String::from(TD3_SETTINGS);
+    settings_str.push_str("\n\n");
+    for (setting, raw) in values.iter() {
+        let value = format!("{}={}", setting.name, setting.format_value(*raw));
+        settings_str.push_str(&format!("{:27} // {}\n", value, setting.range()));
+    }
+    settings_str
+}
+
+// settings file could list only part of settings, the rest is left as is on device
+pub fn string_to_settings(string_settings: String) -> Result<Vec<(&'static Setting, u8)>, Box<dyn Error>> {
+    let mut lines = string_settings.lines();
+    let line = next_nonempty_line(&mut lines);
+    if line != TD3_SETTINGS {
+        return Err(format!("Expecting {}, read: {}", TD3_SETTINGS, line).into());
+    }
+    let mut values = Vec::new();
+    loop {
+        let line = next_nonempty_line(&mut lines);
+        if line.is_empty() {
+            break;
+        }
+        values.push(parse_assignment(&line)?);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // indexes of any settings in file order, every one once, with any value in range
+    fn values_strategy() -> impl Strategy<Value = Vec<(usize, u8)>> {
+        let settings = prop::sample::subsequence((0..SETTINGS.len()).collect::<Vec<usize>>(), 0..=SETTINGS.len());
+        (settings, prop::collection::vec(any::<u8>(), SETTINGS.len()))
+            .prop_map(|(indexes, raws)| indexes.into_iter().map(|n| (n, raws[n] % (SETTINGS[n].max + 1))).collect())
+    }
+
+    proptest! {
+        #[test]
+        fn settings_round_trip_property(indexes in values_strategy()) {
+            let values = indexes.iter().map(|(n, raw)| (&SETTINGS[*n], *raw)).collect::<Vec<(&Setting, u8)>>();
+            let text = settings_to_string(&values);
+            let read = string_to_settings(text.clone()).unwrap();
+            prop_assert_eq!(values_names(&read), values_names(&values));
+            prop_assert_eq!(settings_to_string(&read), text);
+        }
+
+        #[test]
+        fn value_round_trip_property(n in 0..SETTINGS.len(), raw in any::<u8>()) {
+            let setting = &SETTINGS[n];
+            let raw = raw % (setting.max + 1);
+            prop_assert_eq!(setting.parse_value(&setting.format_value(raw)).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn values_are_parsed_and_formatted() {
+        let channel = find_setting("midi-in-channel").unwrap();
+        assert_eq!(channel.parse_value("1").unwrap(), 0);
+        assert_eq!(channel.parse_value(" 16 ").unwrap(), 15);
+        assert!(channel.parse_value("0").is_err() && channel.parse_value("17").is_err());
+        assert!(channel.parse_value("midi").is_err());
+        assert_eq!(channel.format_value(15), "16");
+        let clock = find_setting("clock-source").unwrap();
+        assert_eq!(clock.parse_value("USB").unwrap(), 2);
+        assert!(clock.parse_value("2").is_err());
+        assert_eq!(clock.format_value(3), "trigger");
+        let velocity = find_setting("accent-velocity").unwrap();
+        assert_eq!(velocity.parse_value("127").unwrap(), 127);
+        assert!(velocity.parse_value("128").is_err());
+        assert!(find_setting("tempo").is_err());
+        assert!(parse_assignment("key-priority").is_err());
+        assert_eq!(parse_assignment("key-priority=last").unwrap().1, 2);
+    }
+
+    #[test]
+    fn settings_file_round_trip() {
+        let values = SETTINGS.iter().map(|s| (s, s.max)).collect::<Vec<(&Setting, u8)>>();
+        let text = settings_to_string(&values);
+        assert!(text.starts_with("TD-3 Settings\n\nmidi-in-channel=16"));
+        assert!(text.contains("clock-source=trigger"));
+        let read = string_to_settings(text).unwrap();
+        assert_eq!(values_names(&read), values_names(&values));
+    }
+
+    fn values_names(values: &[(&Setting, u8)]) -> Vec<(&'static str, u8)> {
+        values.iter().map(|(s, raw)| (s.name, *raw)).collect()
+    }
+
+    #[test]
+    fn settings_file_could_be_partial() {
+        let read = string_to_settings(String::from("TD-3 Settings\n\n// comment\nkey-priority=high\n")).unwrap();
+        assert_eq!(values_names(&read), vec![("key-priority", 1)]);
+        assert!(string_to_settings(String::from("TD-3 Pattern\nkey-priority=high\n")).is_err());
+        assert!(string_to_settings(String::from("TD-3 Settings\nkey-priority=medium\n")).is_err());
+        assert!(string_to_settings(String::from("TD-3 Settings\nkey-priority high\n")).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const GROUPS: u8 = 4;
+// patterns in group on each side
+pub const PATTERNS: u8 = 8;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Side {
+    #[default]
+    A = 0,
+    B = 1,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Side::A => write!(f, "A"),
+            Side::B => write!(f, "B"),
+        }
+    }
+}
+
+// pattern slot on device, group 0-3 and number 0-7 are 0-based like in sysex, written 1-based like 2:3B,
+// fields are in device order: group, then 1A..8A, 1B..8B
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SlotAddress {
+    group: u8,
+    side: Side,
+    number: u8,
+}
+
+impl SlotAddress {
+    pub fn new(group: u8, number: u8, side: Side) -> Result<SlotAddress, Box<dyn Error>> {
+        if group >= GROUPS {
+            return Err(format!("Group should be from 1 to {}, read: {}", GROUPS, group as u16 + 1).into());
+        }
+        if number >= PATTERNS {
+            return Err(format!("Pattern should be from 1 to {}, read: {}", PATTERNS, number as u16 + 1).into());
+        }
+        Ok(SlotAddress { group, side, number })
+    }
+
+    pub fn group(&self) -> u8 {
+        self.group
+    }
+
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    // group and pattern bytes of pattern request and reply, B side is bit 3 of pattern byte
+    pub fn from_sysex(group: u8, pattern: u8) -> Result<SlotAddress, Box<dyn Error>> {
+        if pattern > 0x0F {
+            return Err(format!("Pattern byte should be from 00 to 0f, read: {:02x}", pattern).into());
+        }
+        SlotAddress::new(group, pattern & 0x07, if pattern & 0x08 == 0 { Side::A } else { Side::B })
+    }
+
+    pub fn to_sysex(&self) -> [u8; 2] {
+        [self.group, self.number + ((self.side as u8) << 3)]
+    }
+}
+
+impl fmt::Display for SlotAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}{}", self.group + 1, self.number + 1, self.side)
+    }
+}
+
+impl FromStr for SlotAddress {
+    type Err = Box<dyn Error>;
+    fn from_str(input: &str) -> Result<SlotAddress, Self::Err> {
+        match parse_slots(input)?.as_slice() {
+            [slot] => Ok(*slot),
+            _ => Err(format!("Expecting single slot like 1:2A, read: {}", input).into()),
+        }
+    }
+}
+
+// sync directory and metadata sidecars are laid out the same way, dir/group-N/pattern-XY.txt
+pub fn slot_path(dir: &Path, slot: SlotAddress) -> PathBuf {
+    let pattern = format!("pattern-{}{}.txt", slot.number() + 1, slot.side());
+    dir.join(format!("group-{}", slot.group() + 1)).join(pattern)
+}
+
+// all 64 slots in device order: group, then 1A..8A, 1B..8B
+pub fn all_slots() -> impl Iterator<Item = SlotAddress> {
+    (0..GROUPS).flat_map(|group| {
+        [Side::A, Side::B]
+            .into_iter()
+            .flat_map(move |side| (0..PATTERNS).map(move |number| SlotAddress { group, side, number }))
+    })
+}
+
+// slot arguments, old style "<group> <pattern>" pair is accepted too
+pub fn parse_slot_args(args: &[&str]) -> Result<Vec<SlotAddress>, Box<dyn Error>> {
+    if let [group, pattern] = args {
+        if let (Ok(group), Ok((number, side))) = (parse_group(group), parse_pattern(pattern)) {
+            return Ok(vec![SlotAddress::new(group, number, side)?]);
+        }
+    }
+    let mut slots = Vec::new();
+    for arg in args.iter() {
+        for slot in parse_slots(arg)? {
+            if !slots.contains(&slot) {
+                slots.push(slot);
+            }
+        }
+    }
+    Ok(slots)
+}
+
+// 2:3B, g2p3b, 19 (1-64 in device order), 1-16, 2:* and all
+pub fn parse_slots(arg: &str) -> Result<Vec<SlotAddress>, Box<dyn Error>> {
+    let arg = arg.trim().to_lowercase();
+    if arg == "all" {
+        return Ok(all_slots().collect());
+    }
+    if let Some((group, pattern)) = arg.split_once(':') {
+        let group = parse_group(group)?;
+        if pattern == "*" {
+            return Ok(all_slots().filter(|s| s.group == group).collect());
+        }
+        let (number, side) = parse_pattern(pattern)?;
+        return Ok(vec![SlotAddress::new(group, number, side)?]);
+    }
+    if let Some(rest) = arg.strip_prefix('g') {
+        return match rest.split_once('p') {
+            Some((group, pattern)) => {
+                let (number, side) = parse_pattern(pattern)?;
+                Ok(vec![SlotAddress::new(parse_group(group)?, number, side)?])
+            }
+            None => Err(format!("Expecting slot like g2p3b, read: {}", arg).into()),
+        };
+    }
+    if let Some((first, last)) = arg.split_once('-') {
+        let (first, last) = (parse_index(first)?, parse_index(last)?);
+        if first > last {
+            return Err(format!("Slot range {} is reversed", arg).into());
+        }
+        return Ok(all_slots().skip(first).take(last - first + 1).collect());
+    }
+    Ok(all_slots().skip(parse_index(&arg)?).take(1).collect())
+}
+
+// linear slot number 1-64 -> 0-based index in device order
+fn parse_index(arg: &str) -> Result<usize, Box<dyn Error>> {
+    match arg.parse::<usize>() {
+        Ok(index) if (1..=64).contains(&index) => Ok(index - 1),
+        _ => Err(format!("Slot should be like 2:3B, g2p3b, number 1-64, 1-16, 2:* or all, read: {}", arg).into()),
+    }
+}
+
+// group
+pub(crate) fn parse_group(arg: &str) -> Result<u8, Box<dyn Error>> {
+    match arg.parse::<u8>() {
+        Ok(group) => match group {
+            1..=4 => Ok(group - 1),
+            _ => Err("Group should be from 1 to 4".into()),
+        },
+        _ => Err(format!("Group {:?} is invalid", arg.parse::<u8>()).into()),
+    }
+}
+
+// pattern number|ab
+fn parse_pattern(arg: &str) -> Result<(u8, Side), Box<dyn Error>> {
+    let chars = arg.chars().collect::<Vec<char>>();
+    if chars.len() != 2 {
+        return Err("Pattern should consist of number from 1 to 8 and letter A or B".into());
+    }
+    let number = match chars[0].to_digit(10) {
+        Some(pattern @ 1..=8) => pattern as u8 - 1,
+        Some(_) => return Err("Pattern should start with number from 1 to 8".into()),
+        None => return Err("Pattern should start with number".into()),
+    };
+    let side = match chars[1] {
+        'A' | 'a' => Side::A,
+        'B' | 'b' => Side::B,
+        _ => return Err("Pattern should end with letter A or B".into()),
+    };
+    Ok((number, side))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slots(args: &[&str]) -> Vec<String> {
+        parse_slot_args(args).unwrap().iter().map(|slot| slot.to_string()).collect()
+    }
+
+    #[test]
+    fn slot_syntax() {
+        assert_eq!(slots(&["2:3B"]), ["2:3B"]);
+        assert_eq!(slots(&["g2p3b"]), ["2:3B"]);
+        assert_eq!(slots(&["G2P3B"]), ["2:3B"]);
+        // device order is 1A..8A, 1B..8B of group 1, then group 2
+        assert_eq!(slots(&["19"]), ["2:3A"]);
+        assert_eq!(slots(&["1-3"]), ["1:1A", "1:2A", "1:3A"]);
+        let range = slots(&["1-16"]);
+        assert_eq!(
+            (range.len(), range[0].as_str(), range[8].as_str(), range[15].as_str()),
+            (16, "1:1A", "1:1B", "1:8B")
+        );
+        assert_eq!(slots(&["2:*"]), slots(&["17-32"]));
+        let all = slots(&["all"]);
+        assert_eq!((all.len(), all[0].as_str(), all[63].as_str()), (64, "1:1A", "4:8B"));
+        // old style group and pattern, two numbers are two slots
+        assert_eq!(slots(&["2", "3B"]), ["2:3B"]);
+        assert_eq!(slots(&["2", "3"]), ["1:2A", "1:3A"]);
+        // repeated slots are taken once, in order of first appearance
+        assert_eq!(slots(&["2:1A", "1-2", "2:1A"]), ["2:1A", "1:1A", "1:2A"]);
+    }
+
+    #[test]
+    fn wrong_slots_are_errors() {
+        for arg in ["5:1A", "1:9A", "1:1C", "1:", "0", "65", "16-1", "1-65", "g2", "g5p1a", "g2p3", "x", ""] {
+            assert!(parse_slots(arg).is_err(), "{}", arg);
+        }
+        assert!(parse_slot_args(&["2", "9B"]).is_err());
+        assert!("1-2".parse::<SlotAddress>().is_err());
+        assert_eq!("g1p2b".parse::<SlotAddress>().unwrap().to_string(), "1:2B");
+    }
+
+    #[test]
+    fn slot_address() {
+        let slots = all_slots().collect::<Vec<SlotAddress>>();
+        assert_eq!(slots.len(), 64);
+        // device order is what all_slots gives
+        assert!(slots.windows(2).all(|w| w[0] < w[1]));
+        for slot in slots {
+            assert_eq!(slot.to_string().parse::<SlotAddress>().unwrap(), slot);
+            let [group, pattern] = slot.to_sysex();
+            assert_eq!(SlotAddress::from_sysex(group, pattern).unwrap(), slot);
+        }
+        assert_eq!("2:3B".parse::<SlotAddress>().unwrap().to_sysex(), [1, 0x0A]);
+        assert!(SlotAddress::new(4, 0, Side::A).is_err() && SlotAddress::new(0, 8, Side::B).is_err());
+        assert!(SlotAddress::from_sysex(0, 0x10).is_err() && "2:*".parse::<SlotAddress>().is_err());
+    }
+}
@@ -1,35 +1,180 @@
 use std::clone::Clone;
 use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
 use std::fmt::Debug;
 use std::str::FromStr;
 
-const EMPTY: &'static str = "";
+use crate::pattern::{parse_note, NOTE};
 
-#[derive(Debug, Copy, Clone)]
+const EMPTY: &str = "";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Step {
-    pub note: u8,
-    pub transpose: Transpose,
+    // pitch is always in range, so note and transpose written from it are too
+    pitch: Pitch,
+    // C written as C^ of the octave below, e.g. C^ DN for C3
+    upper_c: bool,
     pub accent: Accent,
     pub slide: Slide,
     pub time: Time,
 }
 
+// C3, what device has on empty step
 impl Default for Step {
     fn default() -> Step {
-        Step { note: 0, transpose: Transpose::Normal, accent: Accent::Off, slide: Slide::Off, time: Time::Normal }
+        Step {
+            pitch: Pitch { semitones: 12 },
+            upper_c: false,
+            accent: Accent::Off,
+            slide: Slide::Off,
+            time: Time::Normal,
+        }
+    }
+}
+
+// C^ is C an octave up, so the same pitch could be written two ways, e.g. C^ DN and C,
+// step keeps note and transpose as device does and set_pitch picks C
+impl Step {
+    // note 0-12, C-B and C^
+    pub fn from_note(note: u8, transpose: Transpose) -> Result<Step, Box<dyn Error>> {
+        let mut step = Step::default();
+        step.set_note(note, transpose)?;
+        Ok(step)
+    }
+
+    pub fn from_pitch(pitch: Pitch) -> Step {
+        let mut step = Step::default();
+        step.set_pitch(pitch);
+        step
+    }
+
+    pub fn pitch(&self) -> Pitch {
+        self.pitch
+    }
+
+    pub fn set_pitch(&mut self, pitch: Pitch) {
+        self.pitch = pitch;
+        // UP C^ is the only way to write C5
+        self.upper_c = pitch.semitones == PITCH_RANGE;
+    }
+
+    pub fn note(&self) -> u8 {
+        match self.upper_c {
+            true => 12,
+            false => self.pitch.class(),
+        }
+    }
+
+    pub fn transpose(&self) -> Transpose {
+        Transpose::try_from((self.pitch.semitones - self.note()) / 12).unwrap()
+    }
+
+    pub fn set_note(&mut self, note: u8, transpose: Transpose) -> Result<(), Box<dyn Error>> {
+        if note > 12 {
+            return Err(format!("Note should be from 0 (C) to 12 (C^), read: {}", note).into());
+        }
+        self.pitch = Pitch { semitones: transpose as u8 * 12 + note };
+        self.upper_c = note == 12;
+        Ok(())
+    }
+}
+
+// C2 .. C5 like notes with octave in pattern files: DN C is C2, C is C3, UP C is C4 and UP C^ is C5
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pitch {
+    // above C2, 0-36
+    semitones: u8,
+}
+
+pub const LOWEST_OCTAVE: u8 = 2;
+pub const HIGHEST_OCTAVE: u8 = 5;
+const PITCH_RANGE: u8 = 36;
+// C2 in scientific pitch notation, middle C is C4
+const LOWEST_MIDI_NOTE: u8 = 36;
+const A4_MIDI_NOTE: f32 = 69.0;
+const A4_FREQUENCY: f32 = 440.0;
+
+impl Pitch {
+    // class is semitones above C 0-11
+    pub fn new(class: u8, octave: u8) -> Result<Pitch, Box<dyn Error>> {
+        if class > 11 {
+            return Err(format!("Pitch class should be from 0 to 11, read: {}", class).into());
+        }
+        match octave.checked_sub(LOWEST_OCTAVE).map(|o| o as u16 * 12 + class as u16) {
+            Some(semitones) if semitones <= PITCH_RANGE as u16 => Ok(Pitch { semitones: semitones as u8 }),
+            _ => Err(format!(
+                "Pitch should be from C{} to C{}, read: {}{}",
+                LOWEST_OCTAVE, HIGHEST_OCTAVE, NOTE[class as usize], octave
+            )
+            .into()),
+        }
+    }
+
+    pub fn from_midi_note(note: u8) -> Result<Pitch, Box<dyn Error>> {
+        match note.checked_sub(LOWEST_MIDI_NOTE) {
+            Some(semitones) if semitones <= PITCH_RANGE => Ok(Pitch { semitones }),
+            _ => {
+                let (low, high) = (LOWEST_MIDI_NOTE, LOWEST_MIDI_NOTE + PITCH_RANGE);
+                Err(format!("Midi note should be from {} to {}, read: {}", low, high, note).into())
+            }
+        }
+    }
+
+    pub fn class(&self) -> u8 {
+        self.semitones % 12
+    }
+
+    pub fn octave(&self) -> u8 {
+        LOWEST_OCTAVE + self.semitones / 12
+    }
+
+    pub fn midi_note(&self) -> u8 {
+        LOWEST_MIDI_NOTE + self.semitones
+    }
+
+    // equal temperament, A4 is 440 Hz
+    pub fn frequency(&self) -> f32 {
+        A4_FREQUENCY * 2f32.powf((self.midi_note() as f32 - A4_MIDI_NOTE) / 12.0)
+    }
+
+    // note 0-12 and transpose pitch is written with, C5 is UP C^
+    pub fn note(&self) -> (u8, Transpose) {
+        match self.semitones {
+            PITCH_RANGE => (12, Transpose::Up),
+            semitones => (semitones % 12, Transpose::try_from(semitones / 12).unwrap()),
+        }
+    }
+}
+
+// C#3
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", NOTE[self.class() as usize], self.octave())
+    }
+}
+
+// any spelling pattern files accept, octave is required
+impl FromStr for Pitch {
+    type Err = Box<dyn Error>;
+    fn from_str(input: &str) -> Result<Pitch, Self::Err> {
+        match parse_note(input)? {
+            (note, Some(transpose)) => Ok(Step::from_note(note, transpose)?.pitch()),
+            _ => Err(format!("Pitch should have octave like C3, read: {}", input).into()),
+        }
     }
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Transpose {
     Down = 0,
     Normal = 1,
     Up = 2,
 }
 
-const UP: &'static str = "UP";
-const DN: &'static str = "DN";
+pub const UP: &str = "UP";
+pub const DN: &str = "DN";
 
 impl FromStr for Transpose {
     type Err = ();
@@ -48,9 +193,9 @@ impl FromStr for Transpose {
 impl Debug for Transpose {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
-            Transpose::Down => write!(f, "{:2?}", UP),
-            Transpose::Normal => write!(f, "{:2?}", EMPTY),
-            Transpose::Up => write!(f, "{:2?}", DN),
+            Transpose::Down => write!(f, "{:2}", DN),
+            Transpose::Normal => write!(f, "{:2}", EMPTY),
+            Transpose::Up => write!(f, "{:2}", UP),
         }
     }
 }
@@ -68,13 +213,13 @@ impl TryFrom<u8> for Transpose {
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Accent {
     Off = 0,
     On = 1,
 }
 
-const AC: &'static str = "AC";
+pub const AC: &str = "AC";
 
 impl FromStr for Accent {
     type Err = ();
@@ -92,8 +237,8 @@ impl FromStr for Accent {
 impl Debug for Accent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
-            Accent::Off => write!(f, "{:2?}", EMPTY),
-            Accent::On => write!(f, "{:2?}", AC),
+            Accent::Off => write!(f, "{:2}", EMPTY),
+            Accent::On => write!(f, "{:2}", AC),
         }
     }
 }
@@ -110,13 +255,13 @@ impl TryFrom<u8> for Accent {
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Slide {
     Off = 0,
     On = 1,
 }
 
-const SL: &'static str = "SL";
+pub const SL: &str = "SL";
 
 impl FromStr for Slide {
     type Err = ();
@@ -134,8 +279,8 @@ impl FromStr for Slide {
 impl Debug for Slide {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
-            Slide::Off => write!(f, "{:2?}", EMPTY),
-            Slide::On => write!(f, "{:2?}", SL),
+            Slide::Off => write!(f, "{:2}", EMPTY),
+            Slide::On => write!(f, "{:2}", SL),
         }
     }
 }
@@ -152,7 +297,7 @@ impl TryFrom<u8> for Slide {
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Time {
     Tie = 0b00,
     Normal = 0b01,
@@ -160,9 +305,9 @@ pub enum Time {
     Rest = 0b11,
 }
 
-const TI: &'static str = "TI";
-const RE: &'static str = "RE";
-const TR: &'static str = "TR";
+pub const TI: &str = "TI";
+pub const RE: &str = "RE";
+pub const TR: &str = "TR";
 
 impl FromStr for Time {
     type Err = ();
@@ -182,10 +327,10 @@ impl FromStr for Time {
 impl Debug for Time {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
-            Time::Normal => write!(f, "{:2?}", EMPTY),
-            Time::Tie => write!(f, "{:2?}", TI),
-            Time::Rest => write!(f, "{:2?}", RE),
-            Time::TieRest => write!(f, "{:2?}", TR),
+            Time::Normal => write!(f, "{:2}", EMPTY),
+            Time::Tie => write!(f, "{:2}", TI),
+            Time::Rest => write!(f, "{:2}", RE),
+            Time::TieRest => write!(f, "{:2}", TR),
         }
     }
 }
@@ -217,3 +362,46 @@ impl TryFrom<u16> for Time {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_pitch() {
+        for note in 0..=12u8 {
+            for transpose in [Transpose::Down, Transpose::Normal, Transpose::Up] {
+                let step = Step::from_note(note, transpose).unwrap();
+                // spelling is kept, C^ DN stays C^ DN
+                assert_eq!((step.note(), step.transpose()), (note, transpose));
+                let pitch = step.pitch();
+                assert_eq!(pitch.to_string().parse::<Pitch>().unwrap(), pitch);
+                assert_eq!(Pitch::from_midi_note(pitch.midi_note()).unwrap(), pitch);
+                assert_eq!(Pitch::new(pitch.class(), pitch.octave()).unwrap(), pitch);
+                let written = Step::from_pitch(pitch);
+                assert_eq!(written.pitch(), pitch);
+                // C^ is written as C an octave up, except UP C^ which has no other way
+                match (note, transpose) {
+                    (12, Transpose::Down) => assert_eq!((written.note(), written.transpose()), (0, Transpose::Normal)),
+                    (12, Transpose::Normal) => assert_eq!((written.note(), written.transpose()), (0, Transpose::Up)),
+                    _ => assert_eq!((written.note(), written.transpose()), (note, transpose)),
+                }
+            }
+        }
+        assert_eq!("A4".parse::<Pitch>().unwrap().frequency(), 440.0);
+        assert_eq!("C2".parse::<Pitch>().unwrap().midi_note(), 36);
+        assert_eq!("UP C^".parse::<Pitch>().ok(), None);
+        assert_eq!("C5".parse::<Pitch>().unwrap().note(), (12, Transpose::Up));
+        assert!(Pitch::new(1, 5).is_err() && Pitch::new(11, 1).is_err() && Pitch::new(12, 3).is_err());
+    }
+
+    #[test]
+    fn note_out_of_range_is_error() {
+        assert!(Step::from_note(13, Transpose::Down).is_err());
+        let mut step = Step::from_note(12, Transpose::Up).unwrap();
+        assert!(step.set_note(200, Transpose::Normal).is_err());
+        // failed set keeps what step had
+        assert_eq!((step.note(), step.transpose()), (12, Transpose::Up));
+        assert_eq!(Step::default().pitch().to_string(), "C3");
+    }
+}
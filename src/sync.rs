@@ -0,0 +1,197 @@
+use std::error::Error;
+use std::fs::{create_dir_all, read_to_string, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, Direction};
+use crate::device::Profile;
+use crate::document::Document;
+use crate::metadata::{download_metadata, remember_metadata, Metadata};
+use crate::midicomm::{get_pattern, send_sysex};
+use crate::pattern::{
+    decode_pattern, file_to_pattern_with_metadata, pattern_to_string_with_metadata, pattern_to_sysex,
+    string_to_pattern_with_metadata, Decode,
+};
+use crate::slot::{slot_path, SlotAddress};
+
+// single slot to be written either to a file (pull) or to the device (push)
+struct Change {
+    slot: SlotAddress,
+    path: PathBuf,
+    created: bool,
+    string_pattern: String,
+}
+
+pub fn sync(
+    out_conn: &mut midir::MidiOutputConnection,
+    rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    profile: &Profile,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let (direction, slots, dry_run, decode) = (&config.direction, &config.slots, config.dry_run, config.decode);
+    let dir = Path::new(&config.dir);
+    let plan = match direction {
+        Direction::Pull => pull_plan(out_conn, rx, profile, dir, slots, decode)?,
+        Direction::Push => push_plan(out_conn, rx, profile, dir, slots, decode)?,
+    };
+    // plan is the only output of dry run, it's shown before asking and is just status with --yes
+    let plan_str = plan_to_string(direction, dir, &plan);
+    if dry_run {
+        print!("{}", plan_str);
+    } else if config.yes || plan.is_empty() {
+        status!("{}", plan_str.trim_end());
+    } else {
+        eprintln!("{}", plan_str.trim_end());
+    }
+    if dry_run || plan.is_empty() {
+        return Ok(());
+    }
+    if !config.yes && !confirm()? {
+        return Err("Sync is cancelled, nothing is changed, --yes applies the plan without asking".into());
+    }
+    // slot which device doesn't accept is reported, the rest are still uploaded
+    let mut failed = 0;
+    for change in plan.iter() {
+        match direction {
+            Direction::Pull => {
+                if let Some(parent) = change.path.parent() {
+                    create_dir_all(parent)?;
+                }
+                let mut file = File::create(&change.path)?;
+                file.write_all(change.string_pattern.as_bytes())?;
+            }
+            Direction::Push => {
+                let (pattern, metadata) = string_to_pattern_with_metadata(change.string_pattern.clone())?;
+                let pattern_sysex = pattern_to_sysex(&pattern, change.slot);
+                match send_sysex(out_conn, rx, profile, "pattern", pattern_sysex.as_slice()) {
+                    Ok(_) => remember_metadata(&pattern, &metadata, change.slot),
+                    Err(e) => {
+                        eprintln!("Warning: {} is not uploaded to {}: {}", change.path.display(), change.slot, e);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+    }
+    status!(
+        "Sync is done, {} slot(s) {}",
+        plan.len() - failed,
+        if let Direction::Pull = direction { "saved" } else { "uploaded" }
+    );
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} slot(s) are not uploaded", failed).into()),
+    }
+}
+
+// anything but y or yes, including closed stdin, is no
+fn confirm() -> Result<bool, Box<dyn Error>> {
+    eprint!("Apply the plan? [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+// device -> directory for given slots, only files which content would change
+fn pull_plan(
+    out_conn: &mut midir::MidiOutputConnection,
+    rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    profile: &Profile,
+    dir: &Path,
+    slots: &[SlotAddress],
+    decode: Decode,
+) -> Result<Vec<Change>, Box<dyn Error>> {
+    let mut plan = Vec::new();
+    for &slot in slots.iter() {
+        let pattern_sysex = get_pattern(out_conn, rx, profile, slot)?;
+        let pattern = decode_pattern(&pattern_sysex, Some(slot), decode)?;
+        let path = slot_path(dir, slot);
+        // file is left as is while pattern in it is the same, otherwise only pattern lines are replaced,
+        // so comments, annotations and metadata stay; file which isn't a pattern is never overwritten
+        let existing = match read_to_string(&path).map(|content| Document::parse(&content)) {
+            Ok(Ok(document)) => Some(document),
+            Ok(Err(e)) => {
+                eprintln!("Warning: {} is skipped, it's not a pattern file: {}", path.display(), e);
+                continue;
+            }
+            Err(_) => None,
+        };
+        let string_pattern = match existing {
+            Some(mut document) => {
+                let (file_pattern, metadata) = document.pattern()?;
+                if file_pattern == pattern {
+                    continue;
+                }
+                document.set_pattern(&pattern);
+                document.set_metadata(&match metadata.is_empty() {
+                    true => download_metadata(&pattern, slot),
+                    false => Metadata { source: Some(slot), ..metadata },
+                });
+                document.to_string()
+            }
+            None => pattern_to_string_with_metadata(&pattern, &download_metadata(&pattern, slot)),
+        };
+        let created = !path.exists();
+        plan.push(Change { slot, path, created, string_pattern });
+    }
+    Ok(plan)
+}
+
+// directory -> device for given slots, only slots which differ from the device, missing files are skipped
+fn push_plan(
+    out_conn: &mut midir::MidiOutputConnection,
+    rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    profile: &Profile,
+    dir: &Path,
+    slots: &[SlotAddress],
+    decode: Decode,
+) -> Result<Vec<Change>, Box<dyn Error>> {
+    let mut plan = Vec::new();
+    for &slot in slots.iter() {
+        let path = slot_path(dir, slot);
+        let string_pattern = match read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        // files are compared in canonical form, so formatting and comments don't trigger upload,
+        // file which isn't a pattern is skipped like in pull
+        let (pattern, _) = match file_to_pattern_with_metadata(&path.display().to_string(), string_pattern.clone()) {
+            Ok(read) => read,
+            Err(e) => {
+                eprintln!("Warning: {} is skipped, it's not a pattern file: {}", path.display(), e);
+                continue;
+            }
+        };
+        let pattern_sysex = get_pattern(out_conn, rx, profile, slot)?;
+        let device_pattern = decode_pattern(&pattern_sysex, Some(slot), decode)?;
+        if pattern == device_pattern {
+            continue;
+        }
+        plan.push(Change { slot, path, created: false, string_pattern });
+    }
+    Ok(plan)
+}
+
+fn plan_to_string(direction: &Direction, dir: &Path, plan: &[Change]) -> String {
+    if plan.is_empty() {
+        return format!("{} is in sync with the device, nothing to do\n", dir.display());
+    }
+    let mut plan_str = format!("Sync plan, {} slot(s):\n", plan.len());
+    for change in plan.iter() {
+        let (action, preposition) = match direction {
+            Direction::Pull if change.created => ("create", "from"),
+            Direction::Pull => ("update", "from"),
+            Direction::Push => ("upload", "to"),
+        };
+        plan_str.push_str(&format!(
+            "    {} {} {} Group {} Pattern: {}{}\n",
+            action,
+            change.path.display(),
+            preposition,
+            change.slot.group() + 1,
+            change.slot.number() + 1,
+            change.slot.side()
+        ));
+    }
+    plan_str
+}
@@ -0,0 +1,264 @@
+use std::error::Error;
+
+extern crate scan_fmt;
+use scan_fmt::scan_fmt;
+
+use crate::pattern::next_nonempty_line;
+use crate::slot::{Side, SlotAddress};
+
+// number of bars track could hold
+pub const MAX_BARS: usize = 64;
+// tracks 1-7
+pub const TRACKS: u8 = 7;
+// transpose is stored with offset, so -12..+12 semitones fits into 0..24
+const TRANSPOSE_OFFSET: i8 = 12;
+const HEADER_LEN: usize = 4;
+const TRACK_ID: u8 = 0x7B;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Bar {
+    pub slot: SlotAddress,
+    pub transpose: i8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Track {
+    pub bars: Vec<Bar>,
+}
+
+fn nibbles(v: u8) -> [u8; 2] {
+    [(v & 0xF0) >> 4, v & 0x0F]
+}
+
+fn from_nibbles(msg: &[u8], start: usize) -> u8 {
+    (msg[start] << 4) + (msg[start + 1] & 0x0F)
+}
+
+// tnum is the track which was asked for, device echoes it back
+pub fn sysex_to_track(msg: &[u8], tnum: Option<u8>) -> Result<Track, Box<dyn Error>> {
+    if msg.len() < HEADER_LEN + MAX_BARS * 4 {
+        return Err(format!("Track sysex should be {} bytes, {} received", HEADER_LEN + MAX_BARS * 4, msg.len()).into());
+    }
+    if msg[0] != TRACK_ID {
+        return Err(format!("Track sysex should start with {:02x}, read: {:02x}", TRACK_ID, msg[0]).into());
+    }
+    match tnum {
+        Some(tnum) if msg[1] != tnum => {
+            return Err(format!("Track {} was requested, track {} received", tnum as u16 + 1, msg[1] as u16 + 1).into())
+        }
+        _ if msg[1] >= TRACKS => {
+            return Err(format!("Track should be from 1 to {}, read: {}", TRACKS, msg[1] as u16 + 1).into())
+        }
+        _ => (),
+    }
+    let bars = from_nibbles(msg, 2) as usize;
+    if bars > MAX_BARS {
+        return Err(format!("Track has {} bars, maximum is {}", bars, MAX_BARS).into());
+    }
+    let mut track = Track { bars: Vec::with_capacity(bars) };
+    for n in 0..bars {
+        let d = HEADER_LEN + n * 4;
+        // pattern reference is arranged like in pattern request: group in upper nibble, ab in bit 3
+        let reference = from_nibbles(msg, d);
+        let transpose = match from_nibbles(msg, d + 2) as i8 {
+            x @ 0..=24 => x - TRANSPOSE_OFFSET,
+            x => return Err(format!("Bar {:02} has wrong transpose value: {:02x}", n + 1, x).into()),
+        };
+        let slot = match SlotAddress::from_sysex(reference >> 4, reference & 0x0F) {
+            Ok(slot) => slot,
+            Err(_) => return Err(format!("Bar {:02} has wrong pattern reference: {:02x}", n + 1, reference).into()),
+        };
+        track.bars.push(Bar { slot, transpose });
+    }
+    Ok(track)
+}
+
+// output size is 4 + 64 * 4 bytes, unused bars are zeroed
+pub fn track_to_sysex(track: &Track, tnum: u8) -> Result<Vec<u8>, Box<dyn Error>> {
+    if tnum >= TRACKS {
+        return Err(format!("Track should be from 1 to {}, read: {}", TRACKS, tnum as u16 + 1).into());
+    }
+    if track.bars.len() > MAX_BARS {
+        return Err(format!("Track has {} bars, maximum is {}", track.bars.len(), MAX_BARS).into());
+    }
+    if let Some(n) = track.bars.iter().position(|bar| !(-TRANSPOSE_OFFSET..=TRANSPOSE_OFFSET).contains(&bar.transpose))
+    {
+        return Err(format!("Wrong transpose in bar {:02}: {}", n + 1, track.bars[n].transpose).into());
+    }
+    let mut sysex: Vec<u8> = Vec::new();
+    sysex.push(TRACK_ID);
+    sysex.push(tnum);
+    sysex.extend_from_slice(&nibbles(track.bars.len() as u8));
+    for n in 0..MAX_BARS {
+        match track.bars.get(n) {
+            Some(bar) => {
+                let [group, pattern] = bar.slot.to_sysex();
+                sysex.extend_from_slice(&nibbles((group << 4) + pattern));
+                sysex.extend_from_slice(&nibbles((bar.transpose + TRANSPOSE_OFFSET) as u8));
+            }
+            None => sysex.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]),
+        }
+    }
+    Ok(sysex)
+}
+
+const TD3_TRACK: &str = "TD-3 Track";
+const BARS: &str = "Bars";
+
+pub fn track_to_string(track: &Track) -> String {
+    let mut track_str = String::from(TD3_TRACK);
+    track_str.push('\n');
+    track_str.push_str(&format!("{}: {}\n", BARS, track.bars.len()));
+    track_str.push('\n');
+    track_str.push_str("// Bar: Group, Pattern, Transpose\n");
+    for (n, bar) in track.bars.iter().enumerate() {
+        track_str.push_str(&format!(
+            "{:02}:       {}, {}{}, {:+3}\n",
+            n + 1,
+            bar.slot.group() + 1,
+            bar.slot.number() + 1,
+            bar.slot.side(),
+            bar.transpose
+        ));
+    }
+    track_str
+}
+
+pub fn string_to_track(string_track: String) -> Result<Track, Box<dyn Error>> {
+    let mut lines = string_track.lines();
+    // TD-3 Track
+    let line = next_nonempty_line(&mut lines);
+    if line != TD3_TRACK {
+        return Err(format!("Expecting {}, read: {}", TD3_TRACK, line).into());
+    }
+    // Bars
+    let line = next_nonempty_line(&mut lines);
+    let bars = match scan_fmt!(&line, "{[^:]}: {d}", String, usize) {
+        Ok((b, bars)) if b == BARS => bars,
+        _ => return Err(format!("Expecting {}: #, read: {}", BARS, line).into()),
+    };
+    if bars > MAX_BARS {
+        return Err(format!("{} should be from 0 to {}", BARS, MAX_BARS).into());
+    }
+    let mut track = Track { bars: Vec::with_capacity(bars) };
+    for n in 0..bars {
+        let line = next_nonempty_line(&mut lines);
+        let (num, group, pnum, ab, transpose) =
+            match scan_fmt!(&line, "{d}: {d}, {[1-8]}{[ABab]}, {}", usize, u8, u8, String, String) {
+                Ok(x) => x,
+                _ => {
+                    return Err(format!("Expecting bar {:02}: group, pattern, transpose, read: {}", n + 1, line).into())
+                }
+            };
+        if num != n + 1 {
+            return Err(format!("Expecting bar {:02}, read: {}", n + 1, line).into());
+        }
+        if !(1..=4).contains(&group) {
+            return Err(format!("Wrong group in bar {:02}: {}", n + 1, group).into());
+        }
+        let transpose = match transpose.trim_start_matches('+').parse::<i8>() {
+            Ok(x) if (-TRANSPOSE_OFFSET..=TRANSPOSE_OFFSET).contains(&x) => x,
+            _ => return Err(format!("Wrong transpose in bar {:02}: {}", n + 1, transpose).into()),
+        };
+        let side = if ab.to_uppercase() == "A" { Side::A } else { Side::B };
+        track.bars.push(Bar { slot: SlotAddress::new(group - 1, pnum - 1, side)?, transpose });
+    }
+    Ok(track)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::tests::slot_strategy;
+    use proptest::prelude::*;
+
+    fn track_strategy() -> impl Strategy<Value = Track> {
+        let bar = (slot_strategy(), -TRANSPOSE_OFFSET..=TRANSPOSE_OFFSET)
+            .prop_map(|(slot, transpose)| Bar { slot, transpose });
+        prop::collection::vec(bar, 0..=MAX_BARS).prop_map(|bars| Track { bars })
+    }
+
+    proptest! {
+        #[test]
+        fn text_round_trip_property(track in track_strategy()) {
+            let text = track_to_string(&track);
+            let read = string_to_track(text.clone()).unwrap();
+            prop_assert_eq!(&read.bars, &track.bars);
+            prop_assert_eq!(track_to_string(&read), text);
+        }
+
+        #[test]
+        fn sysex_round_trip_property(track in track_strategy(), tnum in 0..TRACKS) {
+            let msg = track_to_sysex(&track, tnum).unwrap();
+            prop_assert_eq!(sysex_to_track(&msg, Some(tnum)).unwrap().bars, track.bars);
+        }
+    }
+
+    fn track() -> Track {
+        let bar =
+            |group, number, side, transpose| Bar { slot: SlotAddress::new(group, number, side).unwrap(), transpose };
+        Track { bars: vec![bar(0, 0, Side::A, 0), bar(3, 7, Side::B, -12), bar(1, 2, Side::A, 12)] }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let text = track_to_string(&track());
+        let read = string_to_track(text.clone()).unwrap();
+        assert_eq!(read.bars, track().bars);
+        assert_eq!(track_to_string(&read), text);
+        assert_eq!(string_to_track(track_to_string(&Default::default())).unwrap().bars, vec![]);
+    }
+
+    #[test]
+    fn sysex_round_trip() {
+        let msg = track_to_sysex(&track(), 2).unwrap();
+        assert_eq!(msg.len(), HEADER_LEN + MAX_BARS * 4);
+        assert_eq!(msg[0..8], [0x7B, 0x02, 0x00, 0x03, 0x00, 0x00, 0x00, 0x0C]);
+        assert_eq!(sysex_to_track(&msg, Some(2)).unwrap().bars, track().bars);
+        assert_eq!(sysex_to_track(&msg, None).unwrap().bars, track().bars);
+    }
+
+    #[test]
+    fn malformed_sysex_is_error() {
+        let msg = track_to_sysex(&track(), 2).unwrap();
+        let changed = |offset: usize, values: &[u8]| {
+            let mut changed = msg.clone();
+            changed[offset..offset + values.len()].copy_from_slice(values);
+            changed
+        };
+        assert!(sysex_to_track(&msg[0..msg.len() - 1], None).is_err());
+        // pattern message, another track and track which doesn't exist
+        assert!(sysex_to_track(&changed(0, &[0x78]), None).is_err());
+        assert!(sysex_to_track(&msg, Some(3)).is_err());
+        assert!(sysex_to_track(&changed(1, &[0x07]), None).is_err());
+        // 65 bars, transpose +13 and group 5 in the first bar
+        assert!(sysex_to_track(&changed(2, &[0x04, 0x01]), None).is_err());
+        assert!(sysex_to_track(&changed(6, &[0x01, 0x09]), None).is_err());
+        assert!(sysex_to_track(&changed(4, &[0x04]), None).is_err());
+    }
+
+    #[test]
+    fn track_out_of_range_is_not_encoded() {
+        let bars = Track { bars: vec![track().bars[0]; MAX_BARS + 1] };
+        assert!(track_to_sysex(&bars, 0).is_err());
+        let transpose = Track { bars: vec![Bar { transpose: 13, ..track().bars[0] }] };
+        assert!(track_to_sysex(&transpose, 0).is_err());
+        assert!(track_to_sysex(&track(), TRACKS).is_err());
+    }
+
+    #[test]
+    fn malformed_text_is_error() {
+        let text = track_to_string(&track());
+        for (from, to) in [
+            ("TD-3 Track", "TD-3 Pattern"),
+            ("Bars: 3", "Bars: 65"),
+            ("Bars: 3", "Bars: 4"),
+            ("02:       4, 8B", "03:       4, 8B"),
+            ("4, 8B", "5, 8B"),
+            ("4, 8B", "4, 9B"),
+            ("-12", "-13"),
+        ] {
+            assert!(string_to_track(text.replace(from, to)).is_err(), "{} -> {}", from, to);
+        }
+    }
+}
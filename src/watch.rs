@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::fs::{metadata, read_to_string};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+use crate::device::Profile;
+use crate::metadata::remember_metadata;
+use crate::midicomm::send_sysex;
+use crate::pattern::{file_to_pattern_with_metadata, pattern_to_sysex};
+use crate::slot::SlotAddress;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn modified(filename: &str) -> Option<SystemTime> {
+    metadata(filename).and_then(|m| m.modified()).ok()
+}
+
+// polls file and uploads it every time it's changed and parsed cleanly, runs until interrupted
+pub fn watch(
+    out_conn: &mut midir::MidiOutputConnection,
+    rx: &std::sync::mpsc::Receiver<std::vec::Vec<u8>>,
+    profile: &Profile,
+    filename: &str,
+    slot: SlotAddress,
+) -> Result<(), Box<dyn Error>> {
+    let slot_name = format!("Group {} Pattern: {}{}", slot.group() + 1, slot.number() + 1, slot.side());
+    status!("Watching {} for {}, press Ctrl+C to stop", filename, slot_name);
+    let mut last_modified = None;
+    let mut last_uploaded = String::from("");
+    // content device didn't accept, it's retried on every poll, but reported once
+    let mut last_failed = String::from("");
+    loop {
+        let current = modified(filename);
+        if current.is_some() && current != last_modified {
+            last_modified = current;
+            // editors could be saving file in several steps, so unreadable file is just skipped till next change
+            let string_pattern = match read_to_string(filename) {
+                Ok(string_pattern) => string_pattern,
+                Err(_) => continue,
+            };
+            if string_pattern != last_uploaded {
+                match file_to_pattern_with_metadata(filename, string_pattern.clone()) {
+                    Ok((pattern, metadata)) => {
+                        let pattern_sysex = pattern_to_sysex(&pattern, slot);
+                        match send_sysex(out_conn, rx, profile, "pattern", pattern_sysex.as_slice()) {
+                            Ok(_) => {
+                                remember_metadata(&pattern, &metadata, slot);
+                                status!("File {} is uploaded to {}", filename, slot_name);
+                                last_uploaded = string_pattern;
+                                last_failed.clear();
+                            }
+                            Err(e) => {
+                                if string_pattern != last_failed {
+                                    eprintln!("File {} is not uploaded, retrying: {}", filename, e);
+                                    last_failed = string_pattern;
+                                }
+                                last_modified = None;
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("File {} is not uploaded:\n{}", filename, e),
+                }
+            }
+        }
+        sleep(POLL_INTERVAL);
+    }
+}
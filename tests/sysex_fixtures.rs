@@ -0,0 +1,56 @@
+// codec check: every fixtures/<name>.syx is pattern sysex in the form device sends it, fixtures/<name>.txt is how it
+// should be written, it's the README example written by hand, not captures from devices,
+// FIXTURES_UPDATE=1 cargo test --test sysex_fixtures writes missing .txt files for new .syx files
+use std::fs::{read, read_dir, read_to_string, write};
+use std::path::{Path, PathBuf};
+
+use td3_pattern::device::{check_response, default_profile};
+use td3_pattern::pattern::{pattern_to_string, pattern_to_sysex, sysex_to_pattern};
+use td3_pattern::slot::SlotAddress;
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures");
+    let mut files = read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "syx"))
+        .collect::<Vec<PathBuf>>();
+    files.sort();
+    files
+}
+
+#[test]
+fn sysex_fixtures_are_written_as_expected() {
+    let files = fixtures();
+    assert!(!files.is_empty(), "no .syx fixtures found");
+    let mut failed = Vec::new();
+    for file in files.iter() {
+        let content = read(file).unwrap();
+        let msg = check_response(&content, default_profile().header, "fixture").unwrap();
+        let pattern = match sysex_to_pattern(&msg) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                failed.push(format!("{}: {}", file.display(), e));
+                continue;
+            }
+        };
+        let text = pattern_to_string(&pattern);
+        let expected_path = file.with_extension("txt");
+        if !expected_path.exists() && std::env::var_os("FIXTURES_UPDATE").is_some() {
+            write(&expected_path, &text).unwrap();
+        }
+        match read_to_string(&expected_path) {
+            Ok(expected) if expected == text => (),
+            Ok(expected) => {
+                failed.push(format!("{}: expected\n{}written\n{}", file.display(), expected, text));
+            }
+            Err(e) => failed.push(format!("{}: {}", expected_path.display(), e)),
+        }
+        // group and pattern are taken from the message, so the same bytes should come back
+        let slot = SlotAddress::from_sysex(msg[1], msg[2]).unwrap();
+        if pattern_to_sysex(&pattern, slot) != msg {
+            failed.push(format!("{}: pattern is not encoded back to the same sysex", file.display()));
+        }
+    }
+    assert!(failed.is_empty(), "{}", failed.join("\n"));
+}